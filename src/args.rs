@@ -1,7 +1,55 @@
 use std::path::PathBuf;
+use std::str::FromStr;
 
+use structopt::clap;
 use structopt::StructOpt;
 
+use crate::chunk_type::ChunkType;
+use crate::compression::Compression;
+use crate::lsb::LsbOptions;
+use crate::png::ChunkPosition;
+use crate::Error;
+
+#[derive(StructOpt)]
+#[structopt(name = "pngme")]
+pub struct Cli {
+    #[structopt(long, global = true, default_value = "text", possible_values = &["text", "json"])]
+    /// Output format: human readable text or JSON objects
+    pub format: Format,
+
+    #[structopt(subcommand)]
+    pub command: Options,
+}
+
+impl Cli {
+    /// Parses the command line, exiting with a usage error when the options do not fit
+    /// the `--method` chosen, which clap cannot check on its own.
+    pub fn parse() -> Cli {
+        let mut cli = Cli::from_args();
+        if let Err(message) = cli.command.check_method() {
+            clap::Error::with_description(message, clap::ErrorKind::ArgumentConflict).exit();
+        }
+        cli
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(Error::InvalidPayload("formato de saida desconhecido")),
+        }
+    }
+}
+
 #[derive(StructOpt)]
 pub enum Options {
     Encode {
@@ -10,25 +58,93 @@ pub enum Options {
         file_path: PathBuf,
 
         #[structopt()]
-        /// Chunk type
-        chunk_type: String,
+        /// Chunk type (may be left out with --method lsb, which does not use it)
+        chunk_type: Option<String>,
 
         #[structopt()]
         /// Message to encode
-        message: String,
+        message: Option<String>,
+
+        #[structopt(parse(from_os_str))]
+        /// Path to output file (optional)
+        output: Option<PathBuf>,
+
+        #[structopt(long, default_value = "chunk", possible_values = &["chunk", "lsb"])]
+        /// Hide the message in a chunk of its own or in the low bits of the pixels
+        method: Method,
+
+        #[structopt(flatten)]
+        encode_args: EncodeArgs,
 
+        #[structopt(flatten)]
+        lsb_args: LsbArgs,
+    },
+    Embed {
         #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+
+        #[structopt()]
+        /// Chunk type
+        chunk_type: String,
+
+        #[structopt(long, parse(from_os_str))]
+        /// File to embed
+        file: PathBuf,
+
+        #[structopt(long)]
+        /// MIME type to record instead of the one guessed from the extension
+        mime: Option<String>,
+
+        #[structopt(long, parse(from_os_str))]
         /// Path to output file (optional)
         output: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        encode_args: EncodeArgs,
     },
     Decode {
         #[structopt(parse(from_os_str))]
         /// Path to file
         file_path: PathBuf,
 
+        #[structopt()]
+        /// Chunk type (not used with --method lsb)
+        chunk_type: Option<String>,
+
+        #[structopt(long, conflicts_with = "index")]
+        /// Decode every message stored under this chunk type
+        all: bool,
+
+        #[structopt(long)]
+        /// Decode the message in the Nth chunk of this type, counting from 0
+        index: Option<usize>,
+
+        #[structopt(long, default_value = "chunk", possible_values = &["chunk", "lsb"])]
+        /// Read the message from a chunk or from the low bits of the pixels
+        method: Method,
+
+        #[structopt(flatten)]
+        decode_args: DecodeArgs,
+
+        #[structopt(flatten)]
+        lsb_args: LsbArgs,
+    },
+    Extract {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+
         #[structopt()]
         /// Chunk type
         chunk_type: String,
+
+        #[structopt(long, parse(from_os_str))]
+        /// Where to write the file (defaults to its original name)
+        out: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        decode_args: DecodeArgs,
     },
     Remove {
         #[structopt(parse(from_os_str))]
@@ -38,6 +154,107 @@ pub enum Options {
         #[structopt()]
         /// Chunk type
         chunk_type: String,
+
+        #[structopt(long)]
+        /// Remove every chunk of this type instead of the first one
+        all: bool,
+    },
+    Sign {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+
+        #[structopt()]
+        /// Chunk type
+        chunk_type: String,
+
+        #[structopt(long, parse(from_os_str))]
+        /// Ed25519 secret key file
+        key: PathBuf,
+    },
+    Verify {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+
+        #[structopt()]
+        /// Chunk type
+        chunk_type: String,
+
+        #[structopt(long, parse(from_os_str))]
+        /// Only accept signatures from the Ed25519 public key in this file
+        pubkey: Option<PathBuf>,
+    },
+    Seal {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+
+        #[structopt(long, parse(from_os_str))]
+        /// Ed25519 secret key file
+        key: PathBuf,
+
+        #[structopt(long)]
+        /// Also cover the ancillary chunks of this type (repeatable)
+        include: Vec<String>,
+    },
+    CheckSeal {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+
+        #[structopt(long, parse(from_os_str))]
+        /// Only accept a seal from the Ed25519 public key in this file
+        pubkey: Option<PathBuf>,
+    },
+    Keygen {
+        #[structopt(parse(from_os_str))]
+        /// Path to write the secret key to (the public key goes to <path>.pub)
+        output: PathBuf,
+
+        #[structopt(long)]
+        /// Create an Ed25519 signing key pair instead of an encryption key pair
+        signing: bool,
+    },
+    Info {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+    },
+    Capacity {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+
+        #[structopt(flatten)]
+        lsb_args: LsbArgs,
+    },
+    Validate {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+    },
+    List {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+
+        #[structopt(long)]
+        /// Print the inventory as JSON (same as --format json)
+        json: bool,
+    },
+    Dump {
+        #[structopt(parse(from_os_str))]
+        /// Path to file
+        file_path: PathBuf,
+
+        #[structopt(long = "chunk", conflicts_with = "index")]
+        /// Only dump the chunks of this type
+        chunk_type: Option<String>,
+
+        #[structopt(long)]
+        /// Only dump the chunk at this index, as numbered by `list`
+        index: Option<usize>,
     },
     Print {
         #[structopt(parse(from_os_str))]
@@ -45,3 +262,291 @@ pub enum Options {
         file_path: PathBuf,
     },
 }
+
+impl Options {
+    /// Checks `encode` and `decode` options against `--method`. The chunk type positional
+    /// is optional with `--method lsb`; as with any optional positional before a required
+    /// one, it is only left out when the message is the single value given.
+    fn check_method(&mut self) -> Result<(), &'static str> {
+        match self {
+            Options::Encode {
+                chunk_type,
+                message,
+                method,
+                encode_args,
+                lsb_args,
+                ..
+            } => match method {
+                Method::Chunk if chunk_type.is_none() || message.is_none() => {
+                    Err("encode needs a chunk type and a message")
+                }
+                Method::Chunk if lsb_args.is_set() => {
+                    Err("--bits-per-channel, --lsb-alpha and --lsb-key need --method lsb")
+                }
+                Method::Chunk => Ok(()),
+                Method::Lsb if encode_args.is_chunk_only_set() => Err(
+                    "--sign-key, --max-chunk-size, --before, --after, --index, --auto-type \
+                     and --force do not apply to --method lsb",
+                ),
+                Method::Lsb => {
+                    if message.is_none() {
+                        *message = chunk_type.take();
+                    }
+                    match chunk_type.as_deref().map(ChunkType::from_str) {
+                        Some(Err(_)) => {
+                            Err("with --method lsb, give the message alone or after a chunk type")
+                        }
+                        _ => Ok(()),
+                    }
+                }
+            },
+            Options::Decode {
+                chunk_type,
+                all,
+                index,
+                method,
+                decode_args,
+                lsb_args,
+                ..
+            } => match method {
+                Method::Chunk if chunk_type.is_none() => Err("decode needs a chunk type"),
+                Method::Chunk if lsb_args.is_set() => {
+                    Err("--bits-per-channel, --lsb-alpha and --lsb-key need --method lsb")
+                }
+                Method::Chunk => Ok(()),
+                Method::Lsb if *all || index.is_some() || decode_args.auto_type => {
+                    Err("--all, --index and --auto-type do not apply to --method lsb")
+                }
+                Method::Lsb => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+// How the message is compressed and encrypted, shared by every way of storing it. Plain
+// comments on the flattened structs, since structopt would show doc comments as the
+// description of each subcommand.
+#[derive(StructOpt)]
+pub struct ProtectArgs {
+    #[structopt(long, env = "PNGME_PASSPHRASE", hide_env_values = true)]
+    /// Encrypt the message with this passphrase
+    pub passphrase: Option<String>,
+
+    #[structopt(long = "recipient", parse(from_os_str), conflicts_with = "passphrase")]
+    /// Encrypt the message to the public key in this file (repeatable)
+    pub recipients: Vec<PathBuf>,
+
+    #[structopt(long)]
+    /// Compress the message before storing it (zlib, or zstd if built with that feature)
+    pub compress: Option<Compression>,
+}
+
+#[derive(StructOpt)]
+pub struct EncodeArgs {
+    #[structopt(flatten)]
+    pub protect_args: ProtectArgs,
+
+    #[structopt(long, parse(from_os_str))]
+    /// Sign the message with the Ed25519 secret key in this file
+    pub sign_key: Option<PathBuf>,
+
+    #[structopt(long)]
+    /// Split messages longer than this many bytes across several chunks (default 1 MiB)
+    pub max_chunk_size: Option<usize>,
+
+    #[structopt(long, conflicts_with_all = &["after", "index"])]
+    /// Insert the message before the first chunk of this type (default: before IEND)
+    pub before: Option<ChunkType>,
+
+    #[structopt(long, conflicts_with = "index")]
+    /// Insert the message after the last chunk of this type
+    pub after: Option<ChunkType>,
+
+    #[structopt(long)]
+    /// Insert the message at this chunk index
+    pub index: Option<usize>,
+
+    #[structopt(long)]
+    /// Treat the chunk type argument as a label and store under a private ancillary type derived from it
+    pub auto_type: bool,
+
+    #[structopt(long)]
+    /// Store the message even if the chunk type is critical
+    pub force: bool,
+}
+
+impl EncodeArgs {
+    /// True if any option only meaningful for a chunk is given.
+    fn is_chunk_only_set(&self) -> bool {
+        self.sign_key.is_some()
+            || self.max_chunk_size.is_some()
+            || self.before.is_some()
+            || self.after.is_some()
+            || self.index.is_some()
+            || self.auto_type
+            || self.force
+    }
+
+    pub fn position(&self) -> ChunkPosition {
+        match (&self.before, &self.after, self.index) {
+            (Some(chunk_type), _, _) => ChunkPosition::Before(*chunk_type),
+            (_, Some(chunk_type), _) => ChunkPosition::After(*chunk_type),
+            (_, _, Some(index)) => ChunkPosition::Index(index),
+            _ => ChunkPosition::BeforeEnd,
+        }
+    }
+}
+
+// Keys and limits needed to undo `ProtectArgs`.
+#[derive(StructOpt)]
+pub struct OpenArgs {
+    #[structopt(long, env = "PNGME_PASSPHRASE", hide_env_values = true)]
+    /// Passphrase used to encrypt the message
+    pub passphrase: Option<String>,
+
+    #[structopt(long, parse(from_os_str))]
+    /// Secret key file to decrypt a message sent to its public key
+    pub identity: Option<PathBuf>,
+
+    #[structopt(long)]
+    /// Refuse compressed messages that inflate to more than this many bytes (default 64 MiB)
+    pub max_inflated_size: Option<usize>,
+}
+
+#[derive(StructOpt)]
+pub struct DecodeArgs {
+    #[structopt(flatten)]
+    pub open_args: OpenArgs,
+
+    #[structopt(long)]
+    /// Treat the chunk type argument as the label given to `encode --auto-type`
+    pub auto_type: bool,
+}
+
+/// Where `encode` hides the message and `decode` looks for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// In an ancillary chunk of its own.
+    Chunk,
+    /// In the least significant bits of the pixels.
+    Lsb,
+}
+
+impl FromStr for Method {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "chunk" => Ok(Method::Chunk),
+            "lsb" => Ok(Method::Lsb),
+            _ => Err(Error::InvalidPayload("metodo desconhecido")),
+        }
+    }
+}
+
+// Which bits of the pixels `--method lsb` uses.
+#[derive(StructOpt)]
+pub struct LsbArgs {
+    #[structopt(long, default_value = "1")]
+    /// Low bits used in each color sample, from 1 to 8
+    pub bits_per_channel: u8,
+
+    #[structopt(long)]
+    /// Also use the alpha channel
+    pub lsb_alpha: bool,
+
+    #[structopt(long, env = "PNGME_LSB_KEY", hide_env_values = true)]
+    /// Spread the message over the pixels in an order derived from this key
+    pub lsb_key: Option<String>,
+}
+
+impl LsbArgs {
+    /// True if any option differs from its default, which only `--method lsb` can use.
+    fn is_set(&self) -> bool {
+        self.bits_per_channel != 1 || self.lsb_alpha || self.lsb_key.is_some()
+    }
+
+    pub fn options(&self) -> LsbOptions<'_> {
+        LsbOptions {
+            bits_per_channel: self.bits_per_channel,
+            include_alpha: self.lsb_alpha,
+            key: self.lsb_key.as_deref().map(str::as_bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, &'static str> {
+        let mut cli = Cli::from_iter_safe(std::iter::once("pngme").chain(args.iter().copied()))
+            .map_err(|_| "clap")?;
+        cli.command.check_method()?;
+        Ok(cli.command)
+    }
+
+    fn encode_positionals(options: Options) -> (Option<String>, Option<String>, Option<PathBuf>) {
+        match options {
+            Options::Encode {
+                chunk_type,
+                message,
+                output,
+                ..
+            } => (chunk_type, message, output),
+            _ => panic!("not an encode command"),
+        }
+    }
+
+    #[test]
+    fn test_lsb_chunk_type_is_optional() {
+        let alone = parse(&["encode", "in.png", "hello", "--method", "lsb"]).unwrap();
+        assert_eq!(
+            encode_positionals(alone),
+            (None, Some("hello".to_string()), None)
+        );
+
+        let full = [
+            "encode", "in.png", "ruSt", "hello", "out.png", "--method", "lsb",
+        ];
+        assert_eq!(
+            encode_positionals(parse(&full).unwrap()),
+            (
+                Some("ruSt".to_string()),
+                Some("hello".to_string()),
+                Some(PathBuf::from("out.png"))
+            )
+        );
+
+        // Without a chunk type, nothing tells the message from the output path.
+        assert!(parse(&[
+            "encode",
+            "in.png",
+            "hello there",
+            "out.png",
+            "--method",
+            "lsb"
+        ])
+        .is_err());
+        assert!(parse(&["decode", "in.png", "--method", "lsb"]).is_ok());
+    }
+
+    #[test]
+    fn test_options_must_fit_the_method() {
+        assert!(parse(&["encode", "in.png", "hello"]).is_err());
+        assert!(parse(&["encode", "in.png", "ruSt", "hello", "--lsb-alpha"]).is_err());
+        assert!(parse(&["encode", "in.png", "hello", "--method", "lsb", "--force"]).is_err());
+        assert!(parse(&["decode", "in.png"]).is_err());
+        assert!(parse(&["decode", "in.png", "--method", "lsb", "--all"]).is_err());
+        assert!(parse(&["decode", "in.png", "ruSt", "--bits-per-channel", "2"]).is_err());
+        assert!(parse(&[
+            "capacity",
+            "in.png",
+            "--bits-per-channel",
+            "2",
+            "--lsb-key",
+            "k"
+        ])
+        .is_ok());
+    }
+}
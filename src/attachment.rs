@@ -0,0 +1,180 @@
+use std::path::Path;
+
+use crate::{Error, Result};
+
+/// Marks a payload holding an embedded file.
+const MAGIC: [u8; 3] = *b"PMf";
+const VERSION: u8 = 1;
+
+/// A file stored byte-for-byte along with its name and a MIME hint.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub file_name: String,
+    pub mime_type: String,
+    pub contents: Vec<u8>,
+}
+
+/// Returns true if `data` starts with the header written by `Attachment::to_bytes`.
+pub fn is_attachment(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+impl Attachment {
+    /// Reads `path`, keeping only its file name and guessing the MIME type from the extension.
+    pub fn read_from(path: &Path) -> Result<Attachment> {
+        let contents = std::fs::read(path)?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mime_type = guess_mime_type(path).to_string();
+        Ok(Attachment {
+            file_name,
+            mime_type,
+            contents,
+        })
+    }
+
+    /// `magic | version | name length (u16) | name | mime length (u8) | mime | size (u64) | contents`
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let name_len = u16::try_from(self.file_name.len())
+            .map_err(|_| Error::InvalidPayload("nome de arquivo longo demais"))?;
+        let mime_len = u8::try_from(self.mime_type.len())
+            .map_err(|_| Error::InvalidPayload("tipo MIME longo demais"))?;
+
+        let mut bytes = Vec::with_capacity(
+            MAGIC.len() + 12 + self.file_name.len() + self.mime_type.len() + self.contents.len(),
+        );
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&name_len.to_be_bytes());
+        bytes.extend_from_slice(self.file_name.as_bytes());
+        bytes.push(mime_len);
+        bytes.extend_from_slice(self.mime_type.as_bytes());
+        bytes.extend_from_slice(&(self.contents.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&self.contents);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Attachment> {
+        if !is_attachment(bytes) || bytes.len() < MAGIC.len() + 1 {
+            return Err(Error::InvalidPayload("arquivo anexado mal formado"));
+        }
+        let version = bytes[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let mut rest = &bytes[MAGIC.len() + 1..];
+        let name_len = u16::from_be_bytes(take(&mut rest, 2)?.try_into().unwrap()) as usize;
+        let file_name = text_field(take(&mut rest, name_len)?)?;
+        let mime_len = take(&mut rest, 1)?[0] as usize;
+        let mime_type = text_field(take(&mut rest, mime_len)?)?;
+        let size = u64::from_be_bytes(take(&mut rest, 8)?.try_into().unwrap());
+        if size != rest.len() as u64 {
+            return Err(Error::InvalidPayload(
+                "tamanho do arquivo anexado nao confere",
+            ));
+        }
+
+        Ok(Attachment {
+            file_name,
+            mime_type,
+            contents: rest.to_vec(),
+        })
+    }
+
+    /// The stored name reduced to its last component, so it cannot point outside the target directory.
+    pub fn safe_file_name(&self) -> Option<&str> {
+        Path::new(&self.file_name)
+            .file_name()
+            .and_then(|name| name.to_str())
+    }
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if rest.len() < len {
+        return Err(Error::InvalidPayload("arquivo anexado incompleto"));
+    }
+    let (field, tail) = rest.split_at(len);
+    *rest = tail;
+    Ok(field)
+}
+
+fn text_field(bytes: &[u8]) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| Error::InvalidPayload("texto invalido"))
+}
+
+fn guess_mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("txt") | Some("log") => "text/plain",
+        Some("json") => "application/json",
+        Some("toml") => "application/toml",
+        Some("yaml") | Some("yml") => "application/yaml",
+        Some("xml") => "application/xml",
+        Some("csv") => "text/csv",
+        Some("html") | Some("htm") => "text/html",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("pem") | Some("key") | Some("pub") => "application/x-pem-file",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_attachment() -> Attachment {
+        Attachment {
+            file_name: "config.json".to_string(),
+            mime_type: "application/json".to_string(),
+            contents: vec![0, 159, 146, 150, 255, b'{', b'}'],
+        }
+    }
+
+    #[test]
+    fn test_attachment_round_trip() {
+        let attachment = testing_attachment();
+        let bytes = attachment.to_bytes().unwrap();
+        assert!(is_attachment(&bytes));
+        assert_eq!(Attachment::from_bytes(&bytes).unwrap(), attachment);
+    }
+
+    #[test]
+    fn test_truncated_attachment() {
+        let bytes = testing_attachment().to_bytes().unwrap();
+        for end in 0..bytes.len() {
+            assert!(Attachment::from_bytes(&bytes[..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_safe_file_name() {
+        let mut attachment = testing_attachment();
+        attachment.file_name = "../../etc/passwd".to_string();
+        assert_eq!(attachment.safe_file_name(), Some("passwd"));
+        attachment.file_name = "..".to_string();
+        assert_eq!(attachment.safe_file_name(), None);
+    }
+
+    #[test]
+    fn test_guess_mime_type() {
+        assert_eq!(
+            guess_mime_type(Path::new("a/report.PDF")),
+            "application/pdf"
+        );
+        assert_eq!(
+            guess_mime_type(Path::new("noextension")),
+            "application/octet-stream"
+        );
+    }
+}
@@ -1,44 +1,567 @@
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+use pngme::args::{
+    Cli, DecodeArgs, EncodeArgs, Format, LsbArgs, Method, OpenArgs, Options, ProtectArgs,
+};
+use pngme::{Error, Result};
+use serde::Serialize;
+use serde_json::json;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
 
-use pngme::args::Options;
-use structopt::StructOpt;
+use pngme::chunk_type::ChunkType;
+use pngme::commands::{
+    auto_chunk_type, check_seal, chunk_type_warnings, decode_all_messages, decode_message,
+    decode_message_lsb, dump_chunks, dump_file, embed_file, encode_message, encode_message_lsb,
+    extract_file, image_info, keygen, list_chunks, lsb_capacity, print_file, remove_all_chunks,
+    remove_chunk, save_to_file, seal_file, sign_chunk, validate_file, verify_chunk, ChunkSummary,
+    DecodeOptions, EncodeOptions,
+};
+use pngme::keys::{to_hex, Identity, Recipient, SigningKey, VerifyingKey};
+use pngme::png::ChunkPosition;
 
-use pngme::commands::{decode_message, encode_message, print_file, remove_chunk, save_to_file};
+fn main() {
+    let cli = Cli::parse();
+    if let Err(err) = run(cli.command, cli.format) {
+        let category = err.category();
+        match cli.format {
+            Format::Text => eprintln!("Erro: {}", err),
+            Format::Json => eprintln!(
+                "{}",
+                json!({
+                    "error": {
+                        "category": category.name(),
+                        "code": category.exit_code(),
+                        "message": err.to_string(),
+                    }
+                })
+            ),
+        }
+        std::process::exit(category.exit_code());
+    }
+}
 
-fn main() -> Result<()> {
-    let opts = Options::from_args();
-    match opts {
+fn run(command: Options, format: Format) -> Result<()> {
+    match command {
         Options::Encode {
             file_path,
             chunk_type,
             message,
             output,
+            method,
+            encode_args,
+            lsb_args,
         } => {
-            let encoded_png = encode_message(&file_path, chunk_type, message)?;
-            if let Some(output_path) = output {
-                save_to_file(output_path, encoded_png)?;
-            } else {
-                save_to_file(file_path, encoded_png)?;
+            // `Cli::parse` makes sure both are given for the chunk method, and the message for lsb.
+            let message = message.ok_or(Error::InvalidPayload("mensagem ausente"))?;
+            if method == Method::Lsb {
+                let protect_args = &encode_args.protect_args;
+                return encode_lsb(
+                    file_path,
+                    chunk_type,
+                    message,
+                    output,
+                    protect_args,
+                    &lsb_args,
+                    format,
+                );
+            }
+            let chunk_type = chunk_type.ok_or(Error::InvalidPayload("tipo de chunk ausente"))?;
+            let keys = EncodeKeys::load(&encode_args.protect_args, encode_args.sign_key.as_ref())?;
+            let (chunk_type, warnings) = target_chunk_type(chunk_type, &encode_args);
+            let message_length = message.len();
+            let encoded_png = encode_message(
+                &file_path,
+                chunk_type.clone(),
+                message,
+                &keys.options(&encode_args),
+            )?;
+            let output = output.unwrap_or(file_path);
+            save_to_file(output.clone(), encoded_png)?;
+            match format {
+                Format::Text => {
+                    print_warnings(&warnings);
+                    if encode_args.auto_type {
+                        println!("Chunk type: {}", chunk_type);
+                    }
+                }
+                Format::Json => print_json(&json!({
+                    "file": output,
+                    "chunk_type": chunk_type,
+                    "message_length": message_length,
+                    "warnings": warnings,
+                }))?,
+            }
+        }
+        Options::Embed {
+            file_path,
+            chunk_type,
+            file,
+            mime,
+            output,
+            encode_args,
+        } => {
+            let keys = EncodeKeys::load(&encode_args.protect_args, encode_args.sign_key.as_ref())?;
+            let (chunk_type, warnings) = target_chunk_type(chunk_type, &encode_args);
+            let encoded_png = embed_file(
+                &file_path,
+                chunk_type.clone(),
+                &file,
+                mime,
+                &keys.options(&encode_args),
+            )?;
+            let output = output.unwrap_or(file_path);
+            save_to_file(output.clone(), encoded_png)?;
+            match format {
+                Format::Text => {
+                    print_warnings(&warnings);
+                    if encode_args.auto_type {
+                        println!("Chunk type: {}", chunk_type);
+                    }
+                }
+                Format::Json => print_json(&json!({
+                    "file": output,
+                    "chunk_type": chunk_type,
+                    "embedded": file,
+                    "warnings": warnings,
+                }))?,
             }
         }
         Options::Decode {
             file_path,
             chunk_type,
+            all,
+            index,
+            method,
+            decode_args,
+            lsb_args,
         } => {
-            let message = decode_message(&file_path, chunk_type)?;
-            println!("{}", message);
+            let identity = load_identity(&decode_args.open_args)?;
+            let options = decode_options(&decode_args.open_args, identity.as_ref());
+            if method == Method::Lsb {
+                if chunk_type.is_some() {
+                    print_warnings(&[LSB_CHUNK_TYPE_WARNING.to_string()]);
+                }
+                let message = decode_message_lsb(&file_path, &options, &lsb_args.options())?;
+                match format {
+                    Format::Text => print_message(&message)?,
+                    Format::Json => {
+                        let (encoding, data) = message_encoding(&message);
+                        print_json(
+                            &json!({ "method": "lsb", "encoding": encoding, "data": data }),
+                        )?;
+                    }
+                }
+                return Ok(());
+            }
+            let chunk_type = chunk_type.ok_or(Error::InvalidPayload("tipo de chunk ausente"))?;
+            let first = index.unwrap_or(0);
+            let chunk_type = source_chunk_type(chunk_type, &decode_args);
+            let messages = if all {
+                decode_all_messages(&file_path, chunk_type.clone(), &options)?
+            } else {
+                vec![decode_message(
+                    &file_path,
+                    chunk_type.clone(),
+                    first,
+                    &options,
+                )?]
+            };
+            if format == Format::Json {
+                let messages: Vec<_> = messages
+                    .iter()
+                    .enumerate()
+                    .map(|(i, message)| {
+                        let (encoding, data) = message_encoding(message);
+                        json!({ "index": first + i, "encoding": encoding, "data": data })
+                    })
+                    .collect();
+                print_json(&json!({ "chunk_type": chunk_type, "messages": messages }))?;
+                return Ok(());
+            }
+            for message in messages {
+                print_message(&message)?;
+            }
+        }
+        Options::Extract {
+            file_path,
+            chunk_type,
+            out,
+            decode_args,
+        } => {
+            let identity = load_identity(&decode_args.open_args)?;
+            let chunk_type = source_chunk_type(chunk_type, &decode_args);
+            let options = decode_options(&decode_args.open_args, identity.as_ref());
+            let (written, attachment) = extract_file(&file_path, chunk_type, out, &options)?;
+            match format {
+                Format::Text => println!(
+                    "Extracted {} ({}, {} bytes) to {}",
+                    attachment.file_name,
+                    attachment.mime_type,
+                    attachment.contents.len(),
+                    written.display()
+                ),
+                Format::Json => print_json(&json!({
+                    "file": written,
+                    "file_name": attachment.file_name,
+                    "mime_type": attachment.mime_type,
+                    "size": attachment.contents.len(),
+                }))?,
+            }
         }
         Options::Remove {
             file_path,
             chunk_type,
+            all,
         } => {
-            let message = remove_chunk(file_path, chunk_type)?;
-            println!("Message removed: {}", message);
+            if all {
+                let count = remove_all_chunks(file_path, chunk_type.clone())?;
+                match format {
+                    Format::Text => println!("Removed {} chunk(s) of type {}", count, chunk_type),
+                    Format::Json => print_json(&json!({
+                        "chunk_type": chunk_type,
+                        "removed": count,
+                    }))?,
+                }
+            } else {
+                let message = remove_chunk(file_path, chunk_type.clone())?;
+                match format {
+                    Format::Text => println!("Message removed: {}", message),
+                    Format::Json => print_json(&json!({
+                        "chunk_type": chunk_type,
+                        "removed": 1,
+                        "message": message,
+                    }))?,
+                }
+            }
+        }
+        Options::Sign {
+            file_path,
+            chunk_type,
+            key,
+        } => {
+            let sign_key = SigningKey::read_from(&key)?;
+            sign_chunk(file_path, chunk_type.clone(), &sign_key)?;
+            let signer = sign_key.verifying_key().fingerprint();
+            match format {
+                Format::Text => println!("Signed by {}", signer),
+                Format::Json => print_json(&json!({ "chunk_type": chunk_type, "signer": signer }))?,
+            }
+        }
+        Options::Verify {
+            file_path,
+            chunk_type,
+            pubkey,
+        } => {
+            let trusted = pubkey
+                .map(|path| VerifyingKey::read_from(&path))
+                .transpose()?;
+            let signer = verify_chunk(&file_path, chunk_type.clone(), trusted.as_ref())?;
+            match format {
+                Format::Text => println!("Valid signature by {}", signer.fingerprint()),
+                Format::Json => print_json(&json!({
+                    "chunk_type": chunk_type,
+                    "valid": true,
+                    "signer": signer.fingerprint(),
+                }))?,
+            }
+        }
+        Options::Seal {
+            file_path,
+            key,
+            include,
+        } => {
+            let sign_key = SigningKey::read_from(&key)?;
+            seal_file(file_path, &sign_key, include)?;
+            let signer = sign_key.verifying_key().fingerprint();
+            match format {
+                Format::Text => println!("Sealed by {}", signer),
+                Format::Json => print_json(&json!({ "signer": signer }))?,
+            }
+        }
+        Options::CheckSeal { file_path, pubkey } => {
+            let trusted = pubkey
+                .map(|path| VerifyingKey::read_from(&path))
+                .transpose()?;
+            let signer = check_seal(&file_path, trusted.as_ref())?;
+            match format {
+                Format::Text => println!("Valid seal by {}", signer.fingerprint()),
+                Format::Json => {
+                    print_json(&json!({ "valid": true, "signer": signer.fingerprint() }))?
+                }
+            }
+        }
+        Options::Keygen { output, signing } => {
+            let public_key = keygen(output.clone(), signing)?;
+            match format {
+                Format::Text => println!("Public key: {}", public_key),
+                Format::Json => print_json(&json!({
+                    "secret_key_file": output,
+                    "public_key_file": format!("{}.pub", output.display()),
+                    "public_key": public_key,
+                }))?,
+            }
+        }
+        Options::Info { file_path } => {
+            let header = image_info(&file_path)?;
+            match format {
+                Format::Text => println!("{}", header),
+                Format::Json => print_json(&json!({
+                    "width": header.width,
+                    "height": header.height,
+                    "bit_depth": header.bit_depth,
+                    "color_type": header.color_type.to_string(),
+                    "color_type_code": header.color_type.to_u8(),
+                    "interlaced": header.is_interlaced(),
+                }))?,
+            }
+        }
+        Options::Capacity {
+            file_path,
+            lsb_args,
+        } => {
+            let lsb_options = lsb_args.options();
+            let capacity = lsb_capacity(&file_path, &lsb_options)?;
+            match format {
+                Format::Text => println!("{} bytes", capacity),
+                Format::Json => print_json(&json!({
+                    "capacity": capacity,
+                    "bits_per_channel": lsb_options.bits_per_channel,
+                    "include_alpha": lsb_options.include_alpha,
+                }))?,
+            }
         }
+        Options::Validate { file_path } => {
+            let diagnostics = validate_file(&file_path)?;
+            if format == Format::Json {
+                let problems: Vec<_> = diagnostics
+                    .iter()
+                    .map(|diagnostic| {
+                        json!({ "index": diagnostic.index(), "message": diagnostic.to_string() })
+                    })
+                    .collect();
+                print_json(&json!({ "valid": problems.is_empty(), "diagnostics": problems }))?;
+            } else {
+                for diagnostic in &diagnostics {
+                    println!("{}", diagnostic);
+                }
+            }
+            if !diagnostics.is_empty() {
+                return Err(Error::ValidationFailed(diagnostics.len()));
+            }
+            if format == Format::Text {
+                println!("Valid PNG");
+            }
+        }
+        Options::List { file_path, json } => {
+            let chunks = list_chunks(&file_path)?;
+            if json || format == Format::Json {
+                print_json(&chunks)?;
+            } else {
+                print_chunk_table(&chunks);
+            }
+        }
+        Options::Dump {
+            file_path,
+            chunk_type,
+            index,
+        } => match format {
+            Format::Text => print!("{}", dump_file(&file_path, chunk_type, index)?),
+            Format::Json => print_json(&json!({
+                "chunks": dump_chunks(&file_path, chunk_type, index)?,
+            }))?,
+        },
         Options::Print { file_path } => {
-            print_file(file_path)?;
+            let text = print_file(file_path)?;
+            match format {
+                Format::Text => println!("{}", text),
+                Format::Json => print_json(&json!({ "text": text }))?,
+            }
         }
     };
     Ok(())
 }
+
+/// Keys read from the files named in the encode options.
+struct EncodeKeys {
+    recipients: Vec<Recipient>,
+    sign_key: Option<SigningKey>,
+}
+
+impl EncodeKeys {
+    fn load(args: &ProtectArgs, sign_key: Option<&PathBuf>) -> Result<EncodeKeys> {
+        let recipients = args
+            .recipients
+            .iter()
+            .map(|path| Recipient::read_from(path))
+            .collect::<Result<Vec<Recipient>>>()?;
+        let sign_key = sign_key
+            .map(|path| SigningKey::read_from(path))
+            .transpose()?;
+        Ok(EncodeKeys {
+            recipients,
+            sign_key,
+        })
+    }
+
+    /// Options that only compress and encrypt, as used when hiding in the pixels.
+    fn protect_options<'a>(&'a self, args: &'a ProtectArgs) -> EncodeOptions<'a> {
+        EncodeOptions {
+            passphrase: args.passphrase.as_deref(),
+            recipients: &self.recipients,
+            sign_key: None,
+            max_chunk_size: None,
+            compression: args.compress,
+            position: ChunkPosition::BeforeEnd,
+            allow_critical: false,
+        }
+    }
+
+    fn options<'a>(&'a self, args: &'a EncodeArgs) -> EncodeOptions<'a> {
+        EncodeOptions {
+            sign_key: self.sign_key.as_ref(),
+            max_chunk_size: args.max_chunk_size,
+            position: args.position(),
+            allow_critical: args.force,
+            ..self.protect_options(&args.protect_args)
+        }
+    }
+}
+
+/// The chunk type to write to, derived from the label with `--auto-type`, and any warnings about it.
+const LSB_CHUNK_TYPE_WARNING: &str = "o tipo de chunk nao e usado com --method lsb";
+
+/// Hides `message` in the pixels of `file_path`; a chunk type, if given, is not used.
+fn encode_lsb(
+    file_path: PathBuf,
+    chunk_type: Option<String>,
+    message: String,
+    output: Option<PathBuf>,
+    protect_args: &ProtectArgs,
+    lsb_args: &LsbArgs,
+    format: Format,
+) -> Result<()> {
+    let warnings: Vec<String> = chunk_type
+        .map(|_| LSB_CHUNK_TYPE_WARNING.to_string())
+        .into_iter()
+        .collect();
+    let keys = EncodeKeys::load(protect_args, None)?;
+    let message_length = message.len();
+    let lsb_options = lsb_args.options();
+    let capacity = lsb_capacity(&file_path, &lsb_options)?;
+    let encoded_png = encode_message_lsb(
+        &file_path,
+        message,
+        &keys.protect_options(protect_args),
+        &lsb_options,
+    )?;
+    let output = output.unwrap_or(file_path);
+    save_to_file(output.clone(), encoded_png)?;
+    match format {
+        Format::Text => {
+            print_warnings(&warnings);
+            println!(
+                "Hid {} bytes in the pixels (capacity {} bytes)",
+                message_length, capacity
+            );
+        }
+        Format::Json => print_json(&json!({
+            "file": output,
+            "method": "lsb",
+            "message_length": message_length,
+            "capacity": capacity,
+            "bits_per_channel": lsb_options.bits_per_channel,
+            "warnings": warnings,
+        }))?,
+    }
+    Ok(())
+}
+
+fn target_chunk_type(chunk_type: String, args: &EncodeArgs) -> (String, Vec<String>) {
+    if args.auto_type {
+        return (auto_chunk_type(&chunk_type).to_string(), Vec::new());
+    }
+    // A type that does not parse is reported by the command itself.
+    let warnings = ChunkType::from_str(&chunk_type)
+        .map(|parsed| chunk_type_warnings(&parsed))
+        .unwrap_or_default();
+    (chunk_type, warnings)
+}
+
+fn source_chunk_type(chunk_type: String, args: &DecodeArgs) -> String {
+    if args.auto_type {
+        auto_chunk_type(&chunk_type).to_string()
+    } else {
+        chunk_type
+    }
+}
+
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("Aviso: {}", warning);
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).map_err(std::io::Error::from)?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn print_chunk_table(chunks: &[ChunkSummary]) {
+    println!(
+        "{:>5} {:>10} {:4} {:>10} {:10} {:5} PREVIEW",
+        "INDEX", "OFFSET", "TYPE", "LENGTH", "CRC", "FLAGS"
+    );
+    for chunk in chunks {
+        let flags: String = [
+            (chunk.critical, 'C'),
+            (chunk.public, 'P'),
+            (chunk.safe_to_copy, 'S'),
+        ]
+        .iter()
+        .map(|&(set, flag)| if set { flag } else { '-' })
+        .collect();
+        println!(
+            "{:>5} {:>10} {:4} {:>10} {:#010x} {:5} {}",
+            chunk.index,
+            chunk.offset,
+            chunk.chunk_type,
+            chunk.length,
+            chunk.crc,
+            flags,
+            chunk.preview
+        );
+    }
+}
+
+fn load_identity(args: &OpenArgs) -> Result<Option<Identity>> {
+    args.identity
+        .as_ref()
+        .map(|path| Identity::read_from(path))
+        .transpose()
+}
+
+fn decode_options<'a>(args: &'a OpenArgs, identity: Option<&'a Identity>) -> DecodeOptions<'a> {
+    DecodeOptions {
+        passphrase: args.passphrase.as_deref(),
+        identity,
+        max_inflated_size: args.max_inflated_size,
+    }
+}
+
+/// A message as UTF-8 text when it is valid, otherwise as hex.
+fn message_encoding(message: &[u8]) -> (&'static str, String) {
+    match std::str::from_utf8(message) {
+        Ok(text) => ("utf8", text.to_string()),
+        Err(_) => ("hex", to_hex(message)),
+    }
+}
+
+/// Prints text messages on a line of their own and anything else as raw bytes.
+fn print_message(message: &[u8]) -> Result<()> {
+    match std::str::from_utf8(message) {
+        Ok(text) => println!("{}", text),
+        Err(_) => std::io::stdout().write_all(message)?,
+    }
+    Ok(())
+}
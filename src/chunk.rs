@@ -1,6 +1,12 @@
+use std::borrow::Cow;
 use std::fmt::Display;
+use std::io::Write;
+use std::str::Utf8Error;
 
+use crate::chunk_ref::ChunkRef;
 use crate::chunk_type::ChunkType;
+use crate::hexdump;
+use crate::Error;
 use crc;
 
 pub struct Chunk {
@@ -11,55 +17,73 @@ pub struct Chunk {
 }
 
 impl TryFrom<&[u8]> for Chunk {
-    type Error = &'static str;
+    type Error = Error;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if value.len() < 12 {
-            return Err("Bytes insuficientes para criar um chunk");
-        }
-
-        let length = u32::from_be_bytes(value[..4].try_into().unwrap());
-
-        let mut type_bytes = [0u8; 4];
-        type_bytes.clone_from_slice(&value[4..8]);
-        let chunk_type = ChunkType::try_from(type_bytes)?;
-
-        let message_end_index = 8 + length as usize;
-        let message = value[8..message_end_index].to_vec();
-
-        let crc = u32::from_be_bytes(
-            value[message_end_index..message_end_index + 4]
-                .try_into()
-                .unwrap(),
-        );
-
-        let crc_iso_3309 = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        let crc_checksum = crc_iso_3309.checksum(&value[4..message_end_index]);
+        ChunkRef::try_from(value).map(Chunk::from)
+    }
+}
 
-        if crc != crc_checksum {
-            return Err("Checksum incorreta");
+impl From<ChunkRef<'_>> for Chunk {
+    fn from(chunk: ChunkRef<'_>) -> Self {
+        Chunk {
+            length: chunk.length(),
+            crc: chunk.crc(),
+            message: chunk.data().to_vec(),
+            chunk_type: *chunk.chunk_type(),
         }
-
-        Ok(Chunk {
-            length,
-            chunk_type,
-            message,
-            crc,
-        })
     }
 }
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.data_as_string() {
-            Ok(message) => write!(f, "{}", message),
-            Err(_) => write!(f, ""),
-        }
+        write!(f, "{}", display_text(&self.chunk_type, &self.message))
     }
 }
 
+/// Shared by `Chunk` and `ChunkRef`.
+pub(crate) fn data_as_string(chunk_type: &ChunkType, data: &[u8]) -> crate::Result<String> {
+    if data.is_empty() {
+        return Err(Error::EmptyData);
+    }
+    if *chunk_type == ChunkType::TEXT {
+        return Ok(latin1_to_string(data));
+    }
+    std::str::from_utf8(data)
+        .map(str::to_string)
+        .map_err(Error::InvalidUtf8)
+}
+
+/// Like `data_as_string`, but replaces invalid UTF-8 instead of failing.
+pub(crate) fn display_text<'a>(chunk_type: &ChunkType, data: &'a [u8]) -> Cow<'a, str> {
+    if *chunk_type == ChunkType::TEXT {
+        Cow::Owned(latin1_to_string(data))
+    } else {
+        String::from_utf8_lossy(data)
+    }
+}
+
+/// Every Latin-1 byte is the Unicode code point of the same value.
+pub(crate) fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
 impl Chunk {
+    /// Largest data length allowed by the PNG specification (2^31 - 1).
+    pub const MAX_LENGTH: u32 = 0x7fff_ffff;
+
+    /// Panics if `data` is longer than `MAX_LENGTH`; use `try_new` for untrusted sizes.
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        let length: u32 = data.len() as u32;
+        Self::try_new(chunk_type, data).expect("chunk data longer than Chunk::MAX_LENGTH")
+    }
+
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> crate::Result<Chunk> {
+        let length = u32::try_from(data.len())
+            .ok()
+            .filter(|&length| length <= Self::MAX_LENGTH)
+            .ok_or(Error::InvalidLength {
+                offset: 0,
+                length: data.len().min(u32::MAX as usize) as u32,
+            })?;
 
         let bytes: Vec<u8> = chunk_type
             .bytes()
@@ -70,12 +94,12 @@ impl Chunk {
         let crc_iso_3309 = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
         let crc_checksum = crc_iso_3309.checksum(&bytes);
 
-        Chunk {
+        Ok(Chunk {
             length,
             chunk_type,
             message: data,
             crc: crc_checksum,
-        }
+        })
     }
 
     pub fn length(&self) -> u32 {
@@ -90,12 +114,17 @@ impl Chunk {
         &self.chunk_type
     }
 
-    pub fn data_as_string(&self) -> Result<String, &'static str> {
-        if self.message.len() == 0 {
-            return Err("Sem dados para mostrar.");
-        }
-        let message_str: String = self.message.iter().map(|&byte| byte as char).collect();
-        Ok(message_str)
+    pub fn data(&self) -> &[u8] {
+        &self.message
+    }
+
+    pub fn data_as_utf8(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.message)
+    }
+
+    /// The data as text: Latin-1 for `tEXt` chunks, as the PNG spec requires, UTF-8 otherwise.
+    pub fn data_as_string(&self) -> crate::Result<String> {
+        data_as_string(&self.chunk_type, &self.message)
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
@@ -108,6 +137,19 @@ impl Chunk {
             .copied()
             .collect()
     }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> crate::Result<()> {
+        writer.write_all(&self.length.to_be_bytes())?;
+        writer.write_all(&self.chunk_type.bytes())?;
+        writer.write_all(&self.message)?;
+        writer.write_all(&self.crc.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Annotated hex dump of the chunk, with offsets counted from its first byte.
+    pub fn hexdump(&self) -> String {
+        hexdump::annotated_chunk(0, &self.chunk_type, &self.message, self.crc)
+    }
 }
 
 #[cfg(test)]
@@ -165,6 +207,33 @@ mod tests {
         assert_eq!(chunk_string, expected_chunk_string);
     }
 
+    #[test]
+    fn test_chunk_utf8_string() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("RuSt").unwrap(),
+            "ação".as_bytes().to_vec(),
+        );
+        assert_eq!(chunk.data_as_utf8().unwrap(), "ação");
+        assert_eq!(chunk.data_as_string().unwrap(), "ação");
+        assert_eq!(chunk.to_string(), "ação");
+    }
+
+    #[test]
+    fn test_chunk_invalid_utf8() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![b'a', 0xff, b'b']);
+        assert_eq!(chunk.data(), [b'a', 0xff, b'b']);
+        assert!(chunk.data_as_utf8().is_err());
+        assert!(matches!(chunk.data_as_string(), Err(Error::InvalidUtf8(_))));
+        assert_eq!(chunk.to_string(), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn test_text_chunk_is_latin1() {
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"a\xe7\xe3o".to_vec());
+        assert_eq!(chunk.data_as_string().unwrap(), "ação");
+        assert_eq!(chunk.to_string(), "ação");
+    }
+
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
@@ -219,6 +288,45 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_invalid_chunk_crc_error() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        let last = chunk_data.len() - 1;
+        chunk_data[last] ^= 1;
+
+        match Chunk::try_from(chunk_data.as_ref()) {
+            Err(Error::CrcMismatch {
+                chunk_type,
+                offset,
+                expected,
+                actual,
+            }) => {
+                assert_eq!(chunk_type.to_string(), "RuSt");
+                assert_eq!(offset, 0);
+                assert_eq!(expected, 2882656334 ^ 1);
+                assert_eq!(actual, 2882656334);
+            }
+            _ => panic!("expected a CRC mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk_data = testing_chunk().as_bytes();
+        for end in 0..chunk_data.len() {
+            assert!(Chunk::try_from(&chunk_data[..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_chunk_length_above_limit() {
+        let chunk_data = [255, 255, 255, 255, 82, 117, 83, 116, 0, 0, 0, 0];
+        assert!(matches!(
+            Chunk::try_from(chunk_data.as_ref()),
+            Err(Error::InvalidLength { .. })
+        ));
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -240,6 +348,17 @@ mod tests {
         let _chunk_string = format!("{}", chunk);
     }
 
+    #[test]
+    fn test_chunk_hexdump() {
+        let dump = testing_chunk().hexdump();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("00000000  00 00 00 2a "));
+        assert!(lines[2].ends_with("|This is where yo|"));
+        assert!(lines[5].starts_with("00000032  ab d1 d8 4e "));
+        assert!(lines[5].ends_with("crc    0xabd1d84e"));
+    }
+
     #[test]
     pub fn test_chunk_as_bytes() {
         let data_length: u32 = 42;
@@ -0,0 +1,154 @@
+use std::fmt::Display;
+use std::str::Utf8Error;
+
+use crate::chunk::{self, Chunk};
+use crate::chunk_type::ChunkType;
+use crate::hexdump;
+use crate::{Error, Result};
+
+/// A chunk that borrows its data from the buffer it was parsed from.
+#[derive(Debug, Clone)]
+pub struct ChunkRef<'a> {
+    offset: usize,
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    crc: u32,
+}
+
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = Error;
+    fn try_from(value: &'a [u8]) -> Result<Self> {
+        ChunkRef::parse(value, 0)
+    }
+}
+
+impl Display for ChunkRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", chunk::display_text(&self.chunk_type, self.data))
+    }
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses the chunk starting at `offset` in `bytes`, checking bounds and CRC.
+    pub fn parse(bytes: &'a [u8], offset: usize) -> Result<ChunkRef<'a>> {
+        let value = bytes.get(offset..).unwrap_or_default();
+        if value.len() < 12 {
+            return Err(Error::Truncated { offset, needed: 12 });
+        }
+
+        let length = u32::from_be_bytes(value[..4].try_into().unwrap());
+        if length > Chunk::MAX_LENGTH {
+            return Err(Error::InvalidLength { offset, length });
+        }
+
+        let data_end_index = 8 + length as usize;
+        if value.len() < data_end_index + 4 {
+            return Err(Error::Truncated {
+                offset,
+                needed: data_end_index + 4,
+            });
+        }
+
+        let mut type_bytes = [0u8; 4];
+        type_bytes.clone_from_slice(&value[4..8]);
+        let chunk_type = ChunkType::try_from(type_bytes)?;
+
+        let crc = u32::from_be_bytes(
+            value[data_end_index..data_end_index + 4]
+                .try_into()
+                .unwrap(),
+        );
+
+        let crc_iso_3309 = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let crc_checksum = crc_iso_3309.checksum(&value[4..data_end_index]);
+
+        if crc != crc_checksum {
+            return Err(Error::CrcMismatch {
+                chunk_type,
+                offset,
+                expected: crc,
+                actual: crc_checksum,
+            });
+        }
+
+        Ok(ChunkRef {
+            offset,
+            chunk_type,
+            data: &value[8..data_end_index],
+            crc,
+        })
+    }
+
+    /// Byte offset of the chunk in the buffer it was parsed from.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn data_as_utf8(&self) -> std::result::Result<&'a str, Utf8Error> {
+        std::str::from_utf8(self.data)
+    }
+
+    /// The data as text: Latin-1 for `tEXt` chunks, UTF-8 otherwise.
+    pub fn data_as_string(&self) -> Result<String> {
+        chunk::data_as_string(&self.chunk_type, self.data)
+    }
+
+    /// Copies the data into an owned `Chunk`.
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::from(self.clone())
+    }
+
+    /// Annotated hex dump of the chunk, with offsets counted from the start of the buffer.
+    pub fn hexdump(&self) -> String {
+        hexdump::annotated_chunk(self.offset, &self.chunk_type, self.data, self.crc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_chunk_ref_borrows_data() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"borrowed".to_vec());
+        let bytes = chunk.as_bytes();
+        let chunk_ref = ChunkRef::try_from(bytes.as_ref()).unwrap();
+
+        assert_eq!(chunk_ref.data().as_ptr(), bytes[8..].as_ptr());
+        assert_eq!(chunk_ref.length(), 8);
+        assert_eq!(chunk_ref.crc(), chunk.crc());
+        assert_eq!(chunk_ref.to_string(), "borrowed");
+        assert_eq!(chunk_ref.to_chunk().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_chunk_ref_parse_at_offset() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"data".to_vec());
+        let mut bytes = vec![0; 5];
+        bytes.extend(chunk.as_bytes());
+        bytes.pop();
+
+        assert!(matches!(
+            ChunkRef::parse(&bytes, 5),
+            Err(Error::Truncated { offset: 5, .. })
+        ));
+        assert!(ChunkRef::parse(&bytes, 100).is_err());
+    }
+}
@@ -2,29 +2,61 @@ use std::convert::TryFrom;
 use std::fmt::Display;
 use std::str::FromStr;
 
-#[derive(Debug)]
+use sha2::{Digest, Sha256};
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
 
+/// How closely a chunk type must follow the naming rules of the spec to be accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Validation {
+    /// Four ASCII letters. Used when reading files, so chunks that set the reserved bit still load.
+    #[default]
+    Lenient,
+    /// Four ASCII letters with the reserved (third) letter uppercase.
+    Strict,
+}
+
+/// Where a chunk type stands in the PNG chunk registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registration {
+    /// Defined by the PNG specification or one of its registered extensions.
+    Registered,
+    /// Registered once, but its use is no longer recommended.
+    Deprecated,
+    /// Has the private bit set, so it belongs to an application rather than the registry.
+    Private,
+    /// Public, but not a registered type.
+    Unregistered,
+}
+
+impl Display for Registration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Registration::Registered => "registered",
+            Registration::Deprecated => "deprecated",
+            Registration::Private => "private",
+            Registration::Unregistered => "unregistered",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = &'static str;
+    type Error = Error;
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
-        Ok(ChunkType { bytes: value })
+        ChunkType::new(value, Validation::Lenient)
     }
 }
 
 impl FromStr for ChunkType {
-    type Err = &'static str;
+    type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 4 {
-            Err("Tipo com tamanho incorreto (deve conter quatro caracteres)")
-        } else {
-            let str_bytes = s.as_bytes();
-            let mut array: [u8; 4] = [0; 4];
-            array.copy_from_slice(&str_bytes);
-            Ok(ChunkType { bytes: array })
-        }
+        ChunkType::parse(s, Validation::Lenient)
     }
 }
 
@@ -35,16 +67,132 @@ impl Display for ChunkType {
     }
 }
 
-impl PartialEq for ChunkType {
-    fn eq(&self, other: &Self) -> bool {
-        self.bytes == other.bytes
+impl ChunkType {
+    const BIT_FIVE_MASK: u8 = 0b00100000;
+
+    // Critical chunks.
+    pub const IHDR: ChunkType = ChunkType::known(b"IHDR");
+    pub const PLTE: ChunkType = ChunkType::known(b"PLTE");
+    pub const IDAT: ChunkType = ChunkType::known(b"IDAT");
+    pub const IEND: ChunkType = ChunkType::known(b"IEND");
+
+    // Color space information.
+    pub const CHRM: ChunkType = ChunkType::known(b"cHRM");
+    pub const GAMA: ChunkType = ChunkType::known(b"gAMA");
+    pub const ICCP: ChunkType = ChunkType::known(b"iCCP");
+    pub const SBIT: ChunkType = ChunkType::known(b"sBIT");
+    pub const SRGB: ChunkType = ChunkType::known(b"sRGB");
+    pub const CICP: ChunkType = ChunkType::known(b"cICP");
+    pub const MDCV: ChunkType = ChunkType::known(b"mDCV");
+    pub const CLLI: ChunkType = ChunkType::known(b"cLLI");
+
+    // Textual information.
+    pub const TEXT: ChunkType = ChunkType::known(b"tEXt");
+    pub const ZTXT: ChunkType = ChunkType::known(b"zTXt");
+    pub const ITXT: ChunkType = ChunkType::known(b"iTXt");
+
+    // Miscellaneous information.
+    pub const BKGD: ChunkType = ChunkType::known(b"bKGD");
+    pub const HIST: ChunkType = ChunkType::known(b"hIST");
+    pub const TRNS: ChunkType = ChunkType::known(b"tRNS");
+    pub const EXIF: ChunkType = ChunkType::known(b"eXIf");
+    pub const PHYS: ChunkType = ChunkType::known(b"pHYs");
+    pub const SPLT: ChunkType = ChunkType::known(b"sPLT");
+    pub const TIME: ChunkType = ChunkType::known(b"tIME");
+
+    // Animation.
+    pub const ACTL: ChunkType = ChunkType::known(b"acTL");
+    pub const FCTL: ChunkType = ChunkType::known(b"fcTL");
+    pub const FDAT: ChunkType = ChunkType::known(b"fdAT");
+
+    // Registered extensions.
+    pub const OFFS: ChunkType = ChunkType::known(b"oFFs");
+    pub const PCAL: ChunkType = ChunkType::known(b"pCAL");
+    pub const SCAL: ChunkType = ChunkType::known(b"sCAL");
+    pub const GIFG: ChunkType = ChunkType::known(b"gIFg");
+    pub const GIFT: ChunkType = ChunkType::known(b"gIFt");
+    pub const GIFX: ChunkType = ChunkType::known(b"gIFx");
+    pub const STER: ChunkType = ChunkType::known(b"sTER");
+    pub const DSIG: ChunkType = ChunkType::known(b"dSIG");
+    pub const FRAC: ChunkType = ChunkType::known(b"fRAc");
+
+    /// Every registered type with its status.
+    const REGISTRY: &[(ChunkType, Registration)] = &[
+        (ChunkType::IHDR, Registration::Registered),
+        (ChunkType::PLTE, Registration::Registered),
+        (ChunkType::IDAT, Registration::Registered),
+        (ChunkType::IEND, Registration::Registered),
+        (ChunkType::CHRM, Registration::Registered),
+        (ChunkType::GAMA, Registration::Registered),
+        (ChunkType::ICCP, Registration::Registered),
+        (ChunkType::SBIT, Registration::Registered),
+        (ChunkType::SRGB, Registration::Registered),
+        (ChunkType::CICP, Registration::Registered),
+        (ChunkType::MDCV, Registration::Registered),
+        (ChunkType::CLLI, Registration::Registered),
+        (ChunkType::TEXT, Registration::Registered),
+        (ChunkType::ZTXT, Registration::Registered),
+        (ChunkType::ITXT, Registration::Registered),
+        (ChunkType::BKGD, Registration::Registered),
+        (ChunkType::HIST, Registration::Registered),
+        (ChunkType::TRNS, Registration::Registered),
+        (ChunkType::EXIF, Registration::Registered),
+        (ChunkType::PHYS, Registration::Registered),
+        (ChunkType::SPLT, Registration::Registered),
+        (ChunkType::TIME, Registration::Registered),
+        (ChunkType::ACTL, Registration::Registered),
+        (ChunkType::FCTL, Registration::Registered),
+        (ChunkType::FDAT, Registration::Registered),
+        (ChunkType::OFFS, Registration::Registered),
+        (ChunkType::PCAL, Registration::Registered),
+        (ChunkType::SCAL, Registration::Registered),
+        (ChunkType::GIFG, Registration::Registered),
+        (ChunkType::GIFT, Registration::Deprecated),
+        (ChunkType::GIFX, Registration::Registered),
+        (ChunkType::STER, Registration::Registered),
+        (ChunkType::DSIG, Registration::Registered),
+        (ChunkType::FRAC, Registration::Registered),
+    ];
+
+    /// For the constants above, which are known to be valid.
+    const fn known(bytes: &[u8; 4]) -> ChunkType {
+        ChunkType { bytes: *bytes }
     }
-}
 
-impl Eq for ChunkType {}
+    pub fn new(bytes: [u8; 4], validation: Validation) -> Result<ChunkType, Error> {
+        let chunk_type = ChunkType { bytes };
+        let valid = match validation {
+            Validation::Lenient => chunk_type.is_alphabetic(),
+            Validation::Strict => chunk_type.is_valid(),
+        };
+        if !valid {
+            return Err(Error::InvalidChunkType(bytes.to_vec()));
+        }
+        Ok(chunk_type)
+    }
 
-impl ChunkType {
-    const BIT_FIVE_MASK: u8 = 0b00100000;
+    pub fn parse(s: &str, validation: Validation) -> Result<ChunkType, Error> {
+        let bytes: [u8; 4] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| Error::InvalidChunkType(s.as_bytes().to_vec()))?;
+        ChunkType::new(bytes, validation)
+    }
+
+    /// A private, ancillary, safe-to-copy type with a valid reserved bit, derived from `seed`
+    /// so the same label or key always gives the same type.
+    pub fn private_ancillary(seed: &[u8]) -> ChunkType {
+        let digest = Sha256::digest(seed);
+        let letter = |byte: u8| b'a' + byte % 26;
+        ChunkType {
+            bytes: [
+                letter(digest[0]),
+                letter(digest[1]),
+                letter(digest[2]).to_ascii_uppercase(),
+                letter(digest[3]),
+            ],
+        }
+    }
 
     pub fn bytes(&self) -> [u8; 4] {
         self.bytes
@@ -67,13 +215,29 @@ impl ChunkType {
     }
 
     pub fn is_valid(&self) -> bool {
-        self.is_reserved_bit_valid()
+        self.is_alphabetic() && self.is_reserved_bit_valid()
     }
 
     pub fn is_err(&self) -> bool {
         let invalid = self.is_reserved_bit_valid();
         !invalid
     }
+
+    pub fn registration(&self) -> Registration {
+        if !self.is_public() {
+            return Registration::Private;
+        }
+        Self::REGISTRY
+            .iter()
+            .find(|(chunk_type, _)| chunk_type == self)
+            .map_or(Registration::Unregistered, |&(_, registration)| {
+                registration
+            })
+    }
+
+    fn is_alphabetic(&self) -> bool {
+        self.bytes.iter().all(u8::is_ascii_alphabetic)
+    }
 }
 
 #[cfg(test)]
@@ -155,9 +319,77 @@ mod tests {
     pub fn test_invalid_chunk_is_valid() {
         let chunk = ChunkType::from_str("Rust").unwrap();
         assert!(!chunk.is_valid());
-
-        let chunk = ChunkType::from_str("Ru1t").unwrap();
         assert!(chunk.is_err());
+
+        assert!(ChunkType::from_str("Ru1t").is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_rejects_non_letters() {
+        for bytes in [*b"Ru1t", *b"Ru t", [82, 117, 0xc3, 0xa7], *b"RuS\0"] {
+            assert!(matches!(
+                ChunkType::try_from(bytes),
+                Err(Error::InvalidChunkType(_))
+            ));
+        }
+        assert!(ChunkType::from_str("RuStY").is_err());
+        assert!(ChunkType::from_str("açã").is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_strict_validation() {
+        assert!(ChunkType::parse("Rust", Validation::Lenient).is_ok());
+        assert!(ChunkType::parse("Rust", Validation::Strict).is_err());
+        assert!(ChunkType::parse("RuSt", Validation::Strict).is_ok());
+        assert!(ChunkType::new(*b"RUST", Validation::Strict).is_ok());
+    }
+
+    #[test]
+    pub fn test_chunk_type_registry() {
+        assert_eq!(ChunkType::from_str("IHDR").unwrap(), ChunkType::IHDR);
+        assert_eq!(ChunkType::TEXT.to_string(), "tEXt");
+        assert_eq!(ChunkType::IDAT.registration(), Registration::Registered);
+        assert_eq!(ChunkType::FRAC.registration(), Registration::Registered);
+        assert_eq!(ChunkType::GIFT.registration(), Registration::Deprecated);
+        assert_eq!(
+            ChunkType::from_str("ruSt").unwrap().registration(),
+            Registration::Private
+        );
+        assert_eq!(
+            ChunkType::from_str("RUST").unwrap().registration(),
+            Registration::Unregistered
+        );
+    }
+
+    #[test]
+    pub fn test_private_ancillary() {
+        for seed in ["", "pngme", "another label"] {
+            let chunk_type = ChunkType::private_ancillary(seed.as_bytes());
+            assert!(!chunk_type.is_critical());
+            assert!(!chunk_type.is_public());
+            assert!(chunk_type.is_valid());
+            assert!(chunk_type.is_safe_to_copy());
+            assert_eq!(chunk_type.registration(), Registration::Private);
+            assert_eq!(chunk_type, ChunkType::private_ancillary(seed.as_bytes()));
+        }
+        assert_ne!(
+            ChunkType::private_ancillary(b"one"),
+            ChunkType::private_ancillary(b"two")
+        );
+    }
+
+    #[test]
+    pub fn test_chunk_type_as_map_key() {
+        let mut counts = std::collections::BTreeMap::new();
+        for chunk_type in [ChunkType::IDAT, ChunkType::IHDR, ChunkType::IDAT] {
+            *counts.entry(chunk_type).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&ChunkType::IDAT], 2);
+        assert_eq!(counts.keys().next(), Some(&ChunkType::IDAT));
+
+        let set: std::collections::HashSet<ChunkType> =
+            [ChunkType::TEXT, ChunkType::TEXT].into_iter().collect();
+        assert_eq!(set.len(), 1);
     }
 
     #[test]
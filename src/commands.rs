@@ -1,67 +1,1163 @@
-use std::error;
-use std::{path::PathBuf, str::FromStr};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png};
+use memmap2::Mmap;
+use serde::Serialize;
 
-fn read_png(path: &PathBuf) -> Result<Png, Box<dyn std::error::Error>> {
-    let png_file = std::fs::read(path)?;
-    let png = Png::try_from(png_file.as_ref())?;
-    Ok(png)
+use crate::attachment::Attachment;
+use crate::chunk_ref::ChunkRef;
+use crate::compression::{self, Compression};
+use crate::envelope::Envelope;
+use crate::ihdr::Ihdr;
+use crate::image::EncoderOptions;
+use crate::keys::{self, Identity, Recipient, SigningKey, VerifyingKey};
+use crate::lsb::{self, LsbOptions};
+use crate::validate::{self, Diagnostic};
+use crate::{chunk, hexdump, signature, split, text};
+use crate::{
+    chunk::Chunk, chunk_type::ChunkType, chunk_type::Registration, crypto, png::ChunkPosition,
+    png::Png, png_ref::PngRef, Error, Result,
+};
+
+fn read_png(path: &PathBuf) -> Result<Png> {
+    let png_file = File::open(path)?;
+    Png::from_reader(BufReader::new(png_file))
+}
+
+/// Maps the file into memory so it can be inspected through a `PngRef` without copying.
+fn map_png(path: &PathBuf) -> Result<Mmap> {
+    let png_file = File::open(path)?;
+    // Safety: the map is only read, and the commands below never write the file while it is mapped.
+    let map = unsafe { Mmap::map(&png_file)? };
+    Ok(map)
 }
 
-pub fn save_to_file(path: PathBuf, png: Png) -> Result<(), std::io::Error> {
-    std::fs::write(path, png.as_bytes())?;
+pub fn save_to_file(path: PathBuf, png: Png) -> Result<()> {
+    let png_file = File::create(path)?;
+    png.write_to(BufWriter::new(png_file))?;
     Ok(())
 }
 
+/// How a payload is protected and laid out when it is written to the file.
+#[derive(Default)]
+pub struct EncodeOptions<'a> {
+    pub passphrase: Option<&'a str>,
+    pub recipients: &'a [Recipient],
+    pub sign_key: Option<&'a SigningKey>,
+    pub max_chunk_size: Option<usize>,
+    pub compression: Option<Compression>,
+    pub position: ChunkPosition,
+    /// Store the payload even if the chunk type is critical.
+    pub allow_critical: bool,
+}
+
+/// Keys available to decrypt a payload.
+#[derive(Default)]
+pub struct DecodeOptions<'a> {
+    pub passphrase: Option<&'a str>,
+    pub identity: Option<&'a Identity>,
+    pub max_inflated_size: Option<usize>,
+}
+
 pub fn encode_message(
     path: &PathBuf,
     c_type: String,
     message: String,
-) -> Result<Png, Box<dyn error::Error>> {
+    options: &EncodeOptions,
+) -> Result<Png> {
     let mut file: Png = read_png(path)?;
-    let type_chunk: ChunkType = ChunkType::from_str(c_type.as_str())?;
-    let new_chunk: Chunk = Chunk::new(type_chunk, message.as_bytes().to_vec());
+    store_payload(
+        &mut file,
+        c_type,
+        message.into_bytes(),
+        Envelope::default(),
+        options,
+    )?;
+    Ok(file)
+}
 
-    file.append_chunk(new_chunk);
+/// Hides the message in the low bits of the pixels instead of in a chunk, re-encoding the
+/// image data. Placement, splitting and signing options do not apply.
+pub fn encode_message_lsb(
+    path: &PathBuf,
+    message: String,
+    options: &EncodeOptions,
+    lsb_options: &LsbOptions,
+) -> Result<Png> {
+    let mut file: Png = read_png(path)?;
+    let payload = protect_payload(message.into_bytes(), Envelope::default(), options)?;
+    let mut image = file.decode_image()?;
+    lsb::embed(&mut image, &payload, lsb_options)?;
+    file.replace_image_data(&image, &EncoderOptions::default())?;
+    Ok(file)
+}
 
+/// Stores the file at `attachment_path` byte-for-byte, along with its name and MIME type.
+pub fn embed_file(
+    path: &PathBuf,
+    c_type: String,
+    attachment_path: &Path,
+    mime_type: Option<String>,
+    options: &EncodeOptions,
+) -> Result<Png> {
+    let mut file: Png = read_png(path)?;
+    let mut attachment = Attachment::read_from(attachment_path)?;
+    if let Some(mime_type) = mime_type {
+        attachment.mime_type = mime_type;
+    }
+    let envelope = Envelope {
+        attachment: true,
+        ..Envelope::default()
+    };
+    store_payload(&mut file, c_type, attachment.to_bytes()?, envelope, options)?;
     Ok(file)
 }
 
-pub fn decode_message(path: &PathBuf, c_type: String) -> Result<String, Box<dyn error::Error>> {
-    let file: Png = read_png(&path)?;
-    if let Some(chunk) = file.chunk_by_type(c_type.as_str()) {
-        Ok(chunk.to_string())
+/// Decodes the message in the `index`-th chunk of `c_type` (0 for the first one).
+/// Returns the message bytes as stored; they are not required to be UTF-8.
+pub fn decode_message(
+    path: &PathBuf,
+    c_type: String,
+    index: usize,
+    options: &DecodeOptions,
+) -> Result<Vec<u8>> {
+    let map = map_png(path)?;
+    let file = PngRef::try_from(&map[..])?;
+    let chunk_type = ChunkType::from_str(&c_type)?;
+    let (envelope, message) = load_payload(&file, &chunk_type, index, options)?;
+    if envelope.attachment {
+        return Err(Error::InvalidPayload(
+            "o chunk contem um arquivo, use extract",
+        ));
+    }
+    Ok(message)
+}
+
+/// Recovers a message hidden by `encode_message_lsb` with the same `lsb_options`.
+pub fn decode_message_lsb(
+    path: &PathBuf,
+    options: &DecodeOptions,
+    lsb_options: &LsbOptions,
+) -> Result<Vec<u8>> {
+    let image = read_png(path)?.decode_image()?;
+    let (envelope, message) = open_payload(lsb::extract(&image, lsb_options)?, options)?;
+    if envelope.attachment {
+        return Err(Error::InvalidPayload("a imagem contem um arquivo"));
+    }
+    Ok(message)
+}
+
+/// Length of the longest plain message `encode_message_lsb` can hide in the file.
+/// Encryption adds its header and tag, and compression changes the size, so with
+/// those options the longest message that fits differs.
+pub fn lsb_capacity(path: &PathBuf, lsb_options: &LsbOptions) -> Result<usize> {
+    lsb::capacity(&image_info(path)?, lsb_options)
+}
+
+/// Decodes every message stored under `c_type`, in file order.
+/// The pieces of a split message count as one message.
+pub fn decode_all_messages(
+    path: &PathBuf,
+    c_type: String,
+    options: &DecodeOptions,
+) -> Result<Vec<Vec<u8>>> {
+    let map = map_png(path)?;
+    let file = PngRef::try_from(&map[..])?;
+    let chunk_type = ChunkType::from_str(&c_type)?;
+
+    let mut messages = Vec::new();
+    for chunk in message_chunks(&file, &chunk_type) {
+        let (envelope, message) = load_chunk_payload(&file, &chunk_type, chunk, options)?;
+        if envelope.attachment {
+            return Err(Error::InvalidPayload(
+                "o chunk contem um arquivo, use extract",
+            ));
+        }
+        messages.push(message);
+    }
+    if messages.is_empty() {
+        return Err(Error::ChunkNotFound(c_type));
+    }
+    Ok(messages)
+}
+
+/// Writes an embedded file to `output`, or to its stored name in the current directory.
+/// The stored name comes from the file, so an existing file by that name is never replaced.
+/// Returns the path written and the attachment's metadata.
+pub fn extract_file(
+    path: &PathBuf,
+    c_type: String,
+    output: Option<PathBuf>,
+    options: &DecodeOptions,
+) -> Result<(PathBuf, Attachment)> {
+    let map = map_png(path)?;
+    let file = PngRef::try_from(&map[..])?;
+    let chunk_type = ChunkType::from_str(&c_type)?;
+    let (envelope, payload) = load_payload(&file, &chunk_type, 0, options)?;
+    if !envelope.attachment {
+        return Err(Error::InvalidPayload(
+            "o chunk contem uma mensagem, use decode",
+        ));
+    }
+    let attachment = Attachment::from_bytes(&payload)?;
+    let output = write_attachment(&attachment, output, Path::new(""))?;
+    Ok((output, attachment))
+}
+
+/// Writes `attachment` to `output`, or under its stored name in `dir` without overwriting.
+fn write_attachment(
+    attachment: &Attachment,
+    output: Option<PathBuf>,
+    dir: &Path,
+) -> Result<PathBuf> {
+    match output {
+        Some(output) => {
+            std::fs::write(&output, &attachment.contents)?;
+            Ok(output)
+        }
+        None => {
+            let output = attachment
+                .safe_file_name()
+                .map(|name| dir.join(name))
+                .ok_or(Error::InvalidPayload("nome de arquivo anexado invalido"))?;
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&output)?
+                .write_all(&attachment.contents)?;
+            Ok(output)
+        }
+    }
+}
+
+/// Compresses, encrypts, splits and signs `payload` as requested, then appends the resulting chunks.
+/// `envelope` says what the payload is; the flags for the other layers are added here.
+fn store_payload(
+    file: &mut Png,
+    c_type: String,
+    payload: Vec<u8>,
+    envelope: Envelope,
+    options: &EncodeOptions,
+) -> Result<()> {
+    let type_chunk: ChunkType = ChunkType::from_str(c_type.as_str())?;
+    if type_chunk.is_critical() && !options.allow_critical {
+        return Err(Error::CriticalChunkType(type_chunk));
+    }
+    let data = protect_payload(payload, envelope, options)?;
+
+    let max_chunk_size = options
+        .max_chunk_size
+        .unwrap_or(split::DEFAULT_MAX_CHUNK_SIZE);
+    let new_chunks: Vec<Chunk> = if data.len() <= max_chunk_size {
+        vec![Chunk::try_new(type_chunk, data)?]
+    } else {
+        split::split_payload(&type_chunk, &data, max_chunk_size)?
+    };
+    // Split pieces carry the hash of the whole payload, so signing the first one covers it all.
+    let signature_chunk = options
+        .sign_key
+        .map(|key| signature::sign_chunk(key, &new_chunks[0]));
+
+    // The pieces and the signature stay together, starting at the requested position.
+    let mut position = options.position.clone();
+    for new_chunk in new_chunks.into_iter().chain(signature_chunk) {
+        let index = file.insert_chunk(new_chunk, &position)?;
+        position = ChunkPosition::Index(index + 1);
+    }
+    Ok(())
+}
+
+/// The type `--auto-type` derives from `label`, skipping the types pngme keeps for itself.
+pub fn auto_chunk_type(label: &str) -> ChunkType {
+    auto_chunk_type_avoiding(
+        label,
+        &[signature::SIGNATURE_CHUNK_TYPE, signature::SEAL_CHUNK_TYPE],
+    )
+}
+
+/// Derives a type from `label`, extending the seed until the type is not in `reserved`.
+fn auto_chunk_type_avoiding(label: &str, reserved: &[&str]) -> ChunkType {
+    let mut seed = label.as_bytes().to_vec();
+    loop {
+        let chunk_type = ChunkType::private_ancillary(&seed);
+        if !reserved.contains(&chunk_type.to_string().as_str()) {
+            return chunk_type;
+        }
+        seed.push(0);
+    }
+}
+
+/// Reasons to think twice before storing a message under `chunk_type`.
+pub fn chunk_type_warnings(chunk_type: &ChunkType) -> Vec<String> {
+    let mut warnings = Vec::new();
+    match chunk_type.registration() {
+        Registration::Registered => warnings.push(format!(
+            "{} e um tipo registrado; leitores vao tentar interpretar a mensagem",
+            chunk_type
+        )),
+        Registration::Deprecated => {
+            warnings.push(format!("{} e um tipo registrado obsoleto", chunk_type))
+        }
+        Registration::Private | Registration::Unregistered => {}
+    }
+    if !chunk_type.is_reserved_bit_valid() {
+        warnings.push(format!(
+            "a terceira letra de {} deveria ser maiuscula (bit reservado)",
+            chunk_type
+        ));
+    }
+    if !chunk_type.is_safe_to_copy() {
+        warnings.push(format!(
+            "editores de imagem descartam chunks {} ao modificar a imagem",
+            chunk_type
+        ));
+    }
+    warnings
+}
+
+/// Reassembles, decrypts and decompresses the `index`-th message stored under `chunk_type`,
+/// counting a split message once, as `decode_all_messages` does.
+fn load_payload(
+    file: &PngRef,
+    chunk_type: &ChunkType,
+    index: usize,
+    options: &DecodeOptions,
+) -> Result<(Envelope, Vec<u8>)> {
+    let chunk = message_chunks(file, chunk_type)
+        .into_iter()
+        .nth(index)
+        .ok_or_else(|| missing_chunk(chunk_type, index))?;
+    load_chunk_payload(file, chunk_type, chunk, options)
+}
+
+/// The first chunk of each message stored under `chunk_type`: every chunk that is not
+/// a piece, and the first piece of each split message.
+fn message_chunks<'a>(file: &'a PngRef, chunk_type: &ChunkType) -> Vec<&'a ChunkRef<'a>> {
+    let mut seen_hashes: Vec<&[u8]> = Vec::new();
+    file.chunks_by_type(chunk_type)
+        .filter(|chunk| match split::piece_hash(chunk.data()) {
+            Some(hash) if seen_hashes.contains(&hash) => false,
+            Some(hash) => {
+                seen_hashes.push(hash);
+                true
+            }
+            None => true,
+        })
+        .collect()
+}
+
+/// Opens the message starting at `chunk`, gathering the other pieces if it was split.
+fn load_chunk_payload(
+    file: &PngRef,
+    chunk_type: &ChunkType,
+    chunk: &ChunkRef,
+    options: &DecodeOptions,
+) -> Result<(Envelope, Vec<u8>)> {
+    let data = match split::piece_hash(chunk.data()) {
+        Some(hash) => split::reassemble(
+            file.chunks_by_type(chunk_type)
+                .map(|chunk| chunk.data())
+                .filter(|data| split::piece_hash(data) == Some(hash)),
+        )?,
+        None => chunk.data().to_vec(),
+    };
+
+    open_payload(data, options)
+}
+
+/// Compresses, then encrypts, as the options ask, and wraps the result in `envelope`
+/// with the flags of the layers applied. A plain message, with no flag to set, is
+/// returned as is.
+fn protect_payload(
+    payload: Vec<u8>,
+    mut envelope: Envelope,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>> {
+    envelope.compressed = options.compression.is_some();
+    envelope.encrypted = options.passphrase.is_some() || !options.recipients.is_empty();
+    let payload = match options.compression {
+        Some(algorithm) => compression::compress(algorithm, &payload)?,
+        None => payload,
+    };
+    let body = match options.passphrase {
+        Some(passphrase) => crypto::seal(passphrase, &payload)?,
+        None if !options.recipients.is_empty() => {
+            crypto::seal_to_recipients(options.recipients, &payload)?
+        }
+        None => payload,
+    };
+    if envelope == Envelope::default() {
+        return Ok(body);
+    }
+    Ok(envelope.wrap(&body))
+}
+
+/// Undoes `protect_payload`, going by the flags of the envelope. Data without an
+/// envelope is a plain message, returned as is.
+fn open_payload(data: Vec<u8>, options: &DecodeOptions) -> Result<(Envelope, Vec<u8>)> {
+    let Some((envelope, body)) = Envelope::open(&data)? else {
+        return Ok((Envelope::default(), data));
+    };
+    if envelope.piece {
+        return Err(Error::InvalidPayload("pedaco dentro de outro pedaco"));
+    }
+
+    let payload = if !envelope.encrypted {
+        body.to_vec()
+    } else if crypto::is_sealed(body) {
+        let passphrase = options.passphrase.ok_or(Error::PassphraseRequired)?;
+        crypto::open(passphrase, body)?
+    } else if crypto::is_sealed_to_recipients(body) {
+        let identity = options.identity.ok_or(Error::IdentityRequired)?;
+        crypto::open_with_identity(identity, body)?
+    } else {
+        return Err(Error::InvalidPayload("cifra desconhecida"));
+    };
+
+    if !envelope.compressed {
+        return Ok((envelope, payload));
+    }
+    let max_size = options
+        .max_inflated_size
+        .unwrap_or(compression::DEFAULT_MAX_INFLATED_SIZE);
+    Ok((envelope, compression::decompress(&payload, max_size)?))
+}
+
+fn missing_chunk(chunk_type: &ChunkType, index: usize) -> Error {
+    if index == 0 {
+        Error::ChunkNotFound(chunk_type.to_string())
     } else {
-        Err("Nenhuma mensagem encotrada".into())
+        Error::ChunkNotFound(format!("{} #{}", chunk_type, index))
     }
 }
 
-pub fn remove_chunk(path: PathBuf, c_type: String) -> Result<String, Box<dyn error::Error>> {
+/// Removes the first chunk of `c_type`, returning the message it held, or the kind of
+/// payload when the message cannot be read without a key.
+pub fn remove_chunk(path: PathBuf, c_type: String) -> Result<String> {
     let mut file: Png = read_png(&path)?;
-    let chunk = file.remove_chunk(c_type.as_str())?;
+    let chunk = file.remove_chunk(&ChunkType::from_str(&c_type)?)?;
     save_to_file(path, file)?;
-    Ok(chunk.to_string())
+    Ok(removed_message(&chunk))
 }
 
-pub fn print_file(path: PathBuf) -> Result<(), Box<dyn error::Error>> {
-    let file: Png = read_png(&path)?;
-    let file_str = file.to_string();
-    println!("{}", file_str);
-    Ok(())
+fn removed_message(chunk: &Chunk) -> String {
+    let text = open_payload(chunk.data().to_vec(), &DecodeOptions::default())
+        .ok()
+        .filter(|(envelope, _)| !envelope.attachment)
+        .and_then(|(_, message)| chunk::data_as_string(chunk.chunk_type(), &message).ok());
+    if let Some(text) = text {
+        return text;
+    }
+    match payload_label(chunk.data()) {
+        Some(label) => format!("[{}]", label),
+        None => keys::to_hex(chunk.data()),
+    }
+}
+
+/// Removes every chunk of `c_type`, returning how many there were.
+pub fn remove_all_chunks(path: PathBuf, c_type: String) -> Result<usize> {
+    let mut file: Png = read_png(&path)?;
+    let removed = file.remove_chunks_by_type(&ChunkType::from_str(&c_type)?);
+    if removed.is_empty() {
+        return Err(Error::ChunkNotFound(c_type));
+    }
+    save_to_file(path, file)?;
+    Ok(removed.len())
+}
+
+/// Signs the first chunk of `c_type` and stores the signature in a companion chunk.
+pub fn sign_chunk(path: PathBuf, c_type: String, sign_key: &SigningKey) -> Result<()> {
+    let mut file: Png = read_png(&path)?;
+    let chunk = file
+        .chunk_by_type(&ChunkType::from_str(&c_type)?)
+        .ok_or(Error::ChunkNotFound(c_type))?;
+    let signature_chunk = signature::sign_chunk(sign_key, chunk);
+    file.append_chunk(signature_chunk);
+    save_to_file(path, file)
+}
+
+/// Checks the signature of the first chunk of `c_type`, returning the signer's key.
+pub fn verify_chunk(
+    path: &PathBuf,
+    c_type: String,
+    trusted: Option<&VerifyingKey>,
+) -> Result<VerifyingKey> {
+    let file: Png = read_png(path)?;
+    signature::verify_chunk(&file, &ChunkType::from_str(&c_type)?, trusted)
+}
+
+/// Seals the critical chunks of the image, plus the ancillary types in `include`.
+pub fn seal_file(path: PathBuf, sign_key: &SigningKey, include: Vec<String>) -> Result<()> {
+    let mut file: Png = read_png(&path)?;
+    let extra = include
+        .iter()
+        .map(|c_type| ChunkType::from_str(c_type))
+        .collect::<Result<Vec<ChunkType>>>()?;
+    signature::seal_png(&mut file, sign_key, &extra)?;
+    save_to_file(path, file)
+}
+
+/// Checks the image seal, returning the signer's key.
+pub fn check_seal(path: &PathBuf, trusted: Option<&VerifyingKey>) -> Result<VerifyingKey> {
+    let file: Png = read_png(path)?;
+    signature::check_png_seal(&file, trusted)
+}
+
+/// Writes a new secret key to `path` and its public key to `path.pub`, refusing to overwrite.
+///
+/// Creates an X25519 encryption key pair, or an Ed25519 signing key pair if `signing` is set.
+/// Returns the public key.
+pub fn keygen(path: PathBuf, signing: bool) -> Result<String> {
+    let (secret, public) = if signing {
+        let signing_key = SigningKey::generate();
+        (
+            signing_key.to_string(),
+            signing_key.verifying_key().to_string(),
+        )
+    } else {
+        let identity = Identity::generate();
+        (identity.to_string(), identity.recipient().to_string())
+    };
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut secret_file = options.open(&path)?;
+    writeln!(secret_file, "# public key: {}", public)?;
+    writeln!(secret_file, "{}", secret)?;
+
+    let mut public_path = path.into_os_string();
+    public_path.push(".pub");
+    let mut public_file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(public_path)?;
+    writeln!(public_file, "{}", public)?;
+
+    Ok(public)
+}
+
+/// Reads the IHDR chunk, which must be the first chunk of the file.
+pub fn image_info(path: &PathBuf) -> Result<Ihdr> {
+    let map = map_png(path)?;
+    let file = PngRef::try_from(&map[..])?;
+    match file.chunks().first() {
+        Some(chunk) if *chunk.chunk_type() == ChunkType::IHDR => Ihdr::from_bytes(chunk.data()),
+        _ => Err(Error::ChunkNotFound(ChunkType::IHDR.to_string())),
+    }
+}
+
+pub fn validate_file(path: &PathBuf) -> Result<Vec<Diagnostic>> {
+    let map = map_png(path)?;
+    let file = PngRef::try_from(&map[..])?;
+    Ok(validate::validate_chunks(
+        file.chunks()
+            .iter()
+            .map(|chunk| (chunk.chunk_type(), chunk.data())),
+    ))
+}
+
+/// One row of `pngme list`.
+#[derive(Serialize)]
+pub struct ChunkSummary {
+    pub index: usize,
+    pub offset: usize,
+    pub chunk_type: String,
+    pub length: u32,
+    pub crc: u32,
+    pub critical: bool,
+    pub public: bool,
+    pub safe_to_copy: bool,
+    pub preview: String,
+}
+
+pub fn list_chunks(path: &PathBuf) -> Result<Vec<ChunkSummary>> {
+    let map = map_png(path)?;
+    let file = PngRef::try_from(&map[..])?;
+    Ok(file
+        .chunks()
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let chunk_type = chunk.chunk_type();
+            ChunkSummary {
+                index,
+                offset: chunk.offset(),
+                chunk_type: chunk_type.to_string(),
+                length: chunk.length(),
+                crc: chunk.crc(),
+                critical: chunk_type.is_critical(),
+                public: chunk_type.is_public(),
+                safe_to_copy: chunk_type.is_safe_to_copy(),
+                preview: preview(chunk),
+            }
+        })
+        .collect())
+}
+
+/// Annotated hex dump of the whole file, of every chunk of `c_type`, or of the chunk at `index`.
+pub fn dump_file(path: &PathBuf, c_type: Option<String>, index: Option<usize>) -> Result<String> {
+    let map = map_png(path)?;
+    let file = PngRef::try_from(&map[..])?;
+    let whole_file = c_type.is_none() && index.is_none();
+
+    let mut dump = String::new();
+    if whole_file {
+        dump.push_str("signature\n");
+        dump.push_str(&hexdump::hexdump(&Png::STANDARD_HEADER, 0));
+    }
+    for (index, chunk) in select_chunks(&file, c_type, index)? {
+        if !dump.is_empty() {
+            dump.push('\n');
+        }
+        dump.push_str(&format!("chunk {}: {}\n", index, chunk.chunk_type()));
+        dump.push_str(&chunk.hexdump());
+    }
+    Ok(dump)
+}
+
+/// The raw fields of a chunk, with the data in hex.
+#[derive(Debug, Serialize)]
+pub struct ChunkDump {
+    pub index: usize,
+    pub offset: usize,
+    pub chunk_type: String,
+    pub length: u32,
+    pub data: String,
+    pub crc: u32,
+}
+
+/// The chunks `dump_file` would show, as fields rather than a hex dump.
+pub fn dump_chunks(
+    path: &PathBuf,
+    c_type: Option<String>,
+    index: Option<usize>,
+) -> Result<Vec<ChunkDump>> {
+    let map = map_png(path)?;
+    let file = PngRef::try_from(&map[..])?;
+    Ok(select_chunks(&file, c_type, index)?
+        .into_iter()
+        .map(|(index, chunk)| ChunkDump {
+            index,
+            offset: chunk.offset(),
+            chunk_type: chunk.chunk_type().to_string(),
+            length: chunk.length(),
+            data: keys::to_hex(chunk.data()),
+            crc: chunk.crc(),
+        })
+        .collect())
+}
+
+/// Every chunk of `c_type`, the chunk at `index`, or all of them, with their indices.
+fn select_chunks<'a>(
+    file: &'a PngRef,
+    c_type: Option<String>,
+    index: Option<usize>,
+) -> Result<Vec<(usize, &'a ChunkRef<'a>)>> {
+    match (c_type, index) {
+        (_, Some(index)) => {
+            let chunk = file
+                .chunks()
+                .get(index)
+                .ok_or_else(|| Error::ChunkNotFound(format!("indice {}", index)))?;
+            Ok(vec![(index, chunk)])
+        }
+        (Some(c_type), None) => {
+            let chunk_type = ChunkType::from_str(&c_type)?;
+            let selected: Vec<_> = file
+                .chunks()
+                .iter()
+                .enumerate()
+                .filter(|(_, chunk)| *chunk.chunk_type() == chunk_type)
+                .collect();
+            if selected.is_empty() {
+                return Err(Error::ChunkNotFound(c_type));
+            }
+            Ok(selected)
+        }
+        (None, None) => Ok(file.chunks().iter().enumerate().collect()),
+    }
+}
+
+const PREVIEW_LEN: usize = 24;
+
+/// A one-line hint of what the chunk holds: the kind of pngme payload, text, or leading bytes in hex.
+fn preview(chunk: &ChunkRef) -> String {
+    let data = chunk.data();
+    let label = if *chunk.chunk_type() == ChunkType::IHDR {
+        return Ihdr::from_bytes(data)
+            .map(|header| {
+                format!(
+                    "{}x{} {}-bit {}",
+                    header.width, header.height, header.bit_depth, header.color_type
+                )
+            })
+            .unwrap_or_else(|_| "[invalid header]".to_string());
+    } else if chunk.chunk_type().to_string() == signature::SIGNATURE_CHUNK_TYPE {
+        "signature"
+    } else if chunk.chunk_type().to_string() == signature::SEAL_CHUNK_TYPE {
+        "image seal"
+    } else if let Some(label) = payload_label(data) {
+        label
+    } else {
+        return text_or_hex_preview(chunk.chunk_type(), data);
+    };
+    format!("[{}]", label)
+}
+
+/// The kind of pngme payload in `data`, or `None` for a plain message.
+fn payload_label(data: &[u8]) -> Option<&'static str> {
+    let (envelope, body) = Envelope::open(data).ok()??;
+    if envelope.piece {
+        Some("piece of a split message")
+    } else if envelope.encrypted && crypto::is_sealed(body) {
+        Some("encrypted with passphrase")
+    } else if envelope.encrypted {
+        Some("encrypted to recipients")
+    } else if envelope.compressed {
+        Some("compressed")
+    } else if envelope.attachment {
+        Some("embedded file")
+    } else {
+        None
+    }
+}
+
+fn text_or_hex_preview(chunk_type: &ChunkType, data: &[u8]) -> String {
+    // Text chunks separate their fields with a null byte.
+    let separator = if text::is_text_chunk_type(chunk_type) {
+        '\0'
+    } else {
+        '\n'
+    };
+    let text = chunk::data_as_string(chunk_type, data).ok().filter(|text| {
+        text.chars()
+            .all(|c| !c.is_control() || matches!(c, '\n' | '\t' | '\r') || c == separator)
+    });
+
+    match text {
+        Some(text) => {
+            let shown: String = text.chars().take(PREVIEW_LEN).collect();
+            let ellipsis = if shown.len() < text.len() { "..." } else { "" };
+            format!("{:?}{}", shown, ellipsis)
+        }
+        None => {
+            let shown = &data[..data.len().min(PREVIEW_LEN / 2)];
+            let ellipsis = if shown.len() < data.len() { "..." } else { "" };
+            format!("{}{}", keys::to_hex(shown), ellipsis)
+        }
+    }
+}
+
+pub fn print_file(path: PathBuf) -> Result<String> {
+    let map = map_png(&path)?;
+    let file = PngRef::try_from(&map[..])?;
+    Ok(file.to_string())
 }
 
 #[cfg(test)]
-#[test]
-fn test_read_file_valid() {
-    let path = PathBuf::from("./ferris.png");
-    let png = read_png(&path);
-    assert!(png.is_ok());
-}
-
-#[test]
-fn test_read_file_invalid() {
-    let path = PathBuf::from("./no_such_file.png");
-    let png = read_png(&path);
-    assert!(png.is_err());
+mod tests {
+    use super::*;
+
+    /// A path under the system temp directory, unique to this process and `name`.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pngme-{}-{}", std::process::id(), name))
+    }
+
+    fn encode_options(passphrase: Option<&str>) -> EncodeOptions<'_> {
+        EncodeOptions {
+            passphrase,
+            recipients: &[],
+            sign_key: None,
+            max_chunk_size: None,
+            compression: None,
+            position: ChunkPosition::BeforeEnd,
+            allow_critical: false,
+        }
+    }
+
+    #[test]
+    fn test_read_file_valid() {
+        let path = PathBuf::from("./ferris.png");
+        let png = read_png(&path);
+        assert!(png.is_ok());
+    }
+
+    #[test]
+    fn test_read_file_invalid() {
+        let path = PathBuf::from("./no_such_file.png");
+        let png = read_png(&path);
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_list_chunks() {
+        let chunks = list_chunks(&PathBuf::from("./ferris.png")).unwrap();
+        let types: Vec<&str> = chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type.as_str())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "RuSt", "IEND"]
+        );
+
+        // Each chunk starts after the length, type, data and CRC of the previous one.
+        assert_eq!(chunks[0].offset, Png::STANDARD_HEADER.len());
+        for pair in chunks.windows(2) {
+            assert_eq!(
+                pair[1].offset,
+                pair[0].offset + 12 + pair[0].length as usize
+            );
+            assert_eq!(pair[1].index, pair[0].index + 1);
+        }
+
+        let flags = |chunk: &ChunkSummary| (chunk.critical, chunk.public, chunk.safe_to_copy);
+        assert_eq!(flags(&chunks[0]), (true, true, false));
+        assert_eq!(flags(&chunks[3]), (false, true, true));
+        assert_eq!(flags(&chunks[5]), (true, false, true));
+        assert_eq!(chunks[0].preview, "50x50 8-bit RGBA");
+        assert_eq!(chunks[5].preview, "\"hey\"");
+    }
+
+    #[test]
+    fn test_preview_kinds() {
+        let mut png = read_png(&PathBuf::from("./ferris.png")).unwrap();
+        let recipients = [Identity::generate().recipient()];
+        let sign_key = SigningKey::generate();
+        let mut store =
+            |c_type: &str, payload: &[u8], envelope: Envelope, options: EncodeOptions| {
+                store_payload(
+                    &mut png,
+                    c_type.to_string(),
+                    payload.to_vec(),
+                    envelope,
+                    &options,
+                )
+                .unwrap()
+            };
+        store(
+            "plAn",
+            b"hello",
+            Envelope::default(),
+            EncodeOptions {
+                sign_key: Some(&sign_key),
+                ..encode_options(None)
+            },
+        );
+        store(
+            "pwDs",
+            b"hello",
+            Envelope::default(),
+            encode_options(Some("pw")),
+        );
+        store(
+            "reCp",
+            b"hello",
+            Envelope::default(),
+            EncodeOptions {
+                recipients: &recipients,
+                ..encode_options(None)
+            },
+        );
+        store(
+            "czIp",
+            b"hello",
+            Envelope::default(),
+            EncodeOptions {
+                compression: Some(Compression::Zlib),
+                ..encode_options(None)
+            },
+        );
+        store(
+            "spLt",
+            &[7; 200],
+            Envelope::default(),
+            EncodeOptions {
+                max_chunk_size: Some(split::PIECE_HEADER_LEN + 100),
+                ..encode_options(None)
+            },
+        );
+        let attachment = Envelope {
+            attachment: true,
+            ..Envelope::default()
+        };
+        store("fiLe", b"file", attachment, encode_options(None));
+        png.append_chunk(text::TextChunk::new("Title", "ferris").to_chunk().unwrap());
+        png.append_chunk(
+            Chunk::try_new(ChunkType::from_str("biNy").unwrap(), vec![0, 1, 2, 0xff]).unwrap(),
+        );
+        png.append_chunk(
+            Chunk::try_new(ChunkType::from_str("loNg").unwrap(), vec![b'x'; 40]).unwrap(),
+        );
+        signature::seal_png(&mut png, &sign_key, &[]).unwrap();
+
+        let bytes = png.as_bytes();
+        let file = PngRef::try_from(&bytes[..]).unwrap();
+        let preview_of = |c_type: &str| {
+            let chunk_type = ChunkType::from_str(c_type).unwrap();
+            preview(file.chunks_by_type(&chunk_type).next().unwrap())
+        };
+        assert_eq!(preview_of("IHDR"), "50x50 8-bit RGBA");
+        assert_eq!(preview_of("plAn"), "\"hello\"");
+        assert_eq!(preview_of("siGn"), "[signature]");
+        assert_eq!(preview_of("seAl"), "[image seal]");
+        assert_eq!(preview_of("pwDs"), "[encrypted with passphrase]");
+        assert_eq!(preview_of("reCp"), "[encrypted to recipients]");
+        assert_eq!(preview_of("czIp"), "[compressed]");
+        assert_eq!(preview_of("spLt"), "[piece of a split message]");
+        assert_eq!(preview_of("fiLe"), "[embedded file]");
+        assert_eq!(preview_of("tEXt"), "\"Title\\0ferris\"");
+        assert_eq!(preview_of("biNy"), "000102ff");
+        assert_eq!(
+            preview_of("loNg"),
+            format!("{:?}...", "x".repeat(PREVIEW_LEN))
+        );
+    }
+
+    #[test]
+    fn test_message_index_counts_split_messages() {
+        let path = temp_path("split-index.png");
+        let split = "split across pieces ".repeat(20);
+        let options = EncodeOptions {
+            max_chunk_size: Some(split::PIECE_HEADER_LEN + 100),
+            ..encode_options(None)
+        };
+        let png = encode_message(
+            &PathBuf::from("./ferris.png"),
+            "ruSt".to_string(),
+            split.clone(),
+            &options,
+        )
+        .unwrap();
+        save_to_file(path.clone(), png).unwrap();
+        let png = encode_message(
+            &path,
+            "ruSt".to_string(),
+            "whole".to_string(),
+            &encode_options(None),
+        )
+        .unwrap();
+        save_to_file(path.clone(), png).unwrap();
+
+        let options = DecodeOptions::default();
+        let all = decode_all_messages(&path, "ruSt".to_string(), &options);
+        let first = decode_message(&path, "ruSt".to_string(), 0, &options);
+        let second = decode_message(&path, "ruSt".to_string(), 1, &options);
+        let third = decode_message(&path, "ruSt".to_string(), 2, &options);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            all.unwrap(),
+            vec![split.clone().into_bytes(), b"whole".to_vec()]
+        );
+        assert_eq!(first.unwrap(), split.as_bytes());
+        assert_eq!(second.unwrap(), b"whole");
+        assert!(matches!(third, Err(Error::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_critical_chunk_type() {
+        let path = PathBuf::from("./ferris.png");
+        let refused = encode_message(
+            &path,
+            "RuSt".to_string(),
+            "message".to_string(),
+            &encode_options(None),
+        );
+        assert!(
+            matches!(refused, Err(Error::CriticalChunkType(chunk_type)) if chunk_type.to_string() == "RuSt")
+        );
+
+        let forced = EncodeOptions {
+            allow_critical: true,
+            ..encode_options(None)
+        };
+        let png =
+            encode_message(&path, "RuSt".to_string(), "message".to_string(), &forced).unwrap();
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        // ferris.png already holds one RuSt chunk.
+        assert_eq!(png.chunks_by_type(&chunk_type).count(), 2);
+    }
+
+    #[test]
+    fn test_chunk_type_warnings() {
+        let warnings =
+            |chunk_type: &str| chunk_type_warnings(&ChunkType::from_str(chunk_type).unwrap());
+        assert!(warnings("ruSt").is_empty());
+
+        let registered = warnings("tEXt");
+        assert_eq!(registered.len(), 1);
+        assert!(registered[0].contains("tipo registrado;"));
+        let deprecated = warnings("gIFt");
+        assert_eq!(deprecated.len(), 1);
+        assert!(deprecated[0].contains("obsoleto"));
+        let reserved = warnings("rust");
+        assert_eq!(reserved.len(), 1);
+        assert!(reserved[0].contains("bit reservado"));
+        let unsafe_to_copy = warnings("ruST");
+        assert_eq!(unsafe_to_copy.len(), 1);
+        assert!(unsafe_to_copy[0].contains("descartam"));
+
+        assert_eq!(warnings("rusT").len(), 2);
+    }
+
+    #[test]
+    fn test_auto_chunk_type_skips_reserved() {
+        let first = auto_chunk_type_avoiding("myapp", &[]);
+        assert_eq!(auto_chunk_type("myapp"), first);
+
+        // When the derived type is taken, the seed grows by a null byte until it is free.
+        let first_name = first.to_string();
+        let second = auto_chunk_type_avoiding("myapp", &[&first_name]);
+        assert_eq!(second, ChunkType::private_ancillary(b"myapp\0"));
+        let second_name = second.to_string();
+        let third = auto_chunk_type_avoiding("myapp", &[&first_name, &second_name]);
+        assert_eq!(third, ChunkType::private_ancillary(b"myapp\0\0"));
+    }
+
+    #[test]
+    fn test_message_like_a_header() {
+        let path = temp_path("like-a-header.png");
+        let message = "PMe is my initials";
+        let png = encode_message(
+            &PathBuf::from("./ferris.png"),
+            "ruSt".to_string(),
+            message.to_string(),
+            &encode_options(None),
+        )
+        .unwrap();
+        // A plain message is stored as is, without an envelope.
+        let chunk = png.chunk_by_type(&ChunkType::from_str("ruSt").unwrap());
+        assert_eq!(chunk.unwrap().data(), message.as_bytes());
+        save_to_file(path.clone(), png).unwrap();
+
+        let decoded = decode_message(&path, "ruSt".to_string(), 0, &DecodeOptions::default());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded.unwrap(), message.as_bytes());
+    }
+
+    #[test]
+    fn test_remove_reports_message() {
+        let path = temp_path("remove.png");
+        let source = PathBuf::from("./ferris.png");
+        let png = encode_message(
+            &source,
+            "ruSt".to_string(),
+            "hello".to_string(),
+            &encode_options(None),
+        )
+        .unwrap();
+        save_to_file(path.clone(), png).unwrap();
+        let png = encode_message(
+            &path,
+            "seCr".to_string(),
+            "hello".to_string(),
+            &encode_options(Some("pw")),
+        )
+        .unwrap();
+        save_to_file(path.clone(), png).unwrap();
+
+        let plain = remove_chunk(path.clone(), "ruSt".to_string());
+        let sealed = remove_chunk(path.clone(), "seCr".to_string());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(plain.unwrap(), "hello");
+        assert_eq!(sealed.unwrap(), "[encrypted with passphrase]");
+    }
+
+    #[test]
+    fn test_extract_keeps_existing_file() {
+        let dir = temp_path("extract");
+        std::fs::create_dir_all(&dir).unwrap();
+        let attachment = Attachment {
+            file_name: "notes.txt".to_string(),
+            mime_type: "text/plain".to_string(),
+            contents: b"from the image".to_vec(),
+        };
+
+        let first = write_attachment(&attachment, None, &dir);
+        let second = write_attachment(&attachment, None, &dir);
+        let explicit = write_attachment(&attachment, Some(dir.join("notes.txt")), &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first.unwrap(), dir.join("notes.txt"));
+        assert!(
+            matches!(second, Err(Error::Io(ref err)) if err.kind() == std::io::ErrorKind::AlreadyExists)
+        );
+        // An explicit --out is the user's choice and may replace the file.
+        assert!(explicit.is_ok());
+    }
+
+    #[test]
+    fn test_lsb_file_round_trip() {
+        let source = PathBuf::from("./ferris.png");
+        let path = temp_path("lsb-round-trip.png");
+        let key = LsbOptions {
+            key: Some(b"key"),
+            ..LsbOptions::default()
+        };
+        let options = EncodeOptions {
+            compression: Some(Compression::Zlib),
+            ..encode_options(Some("pw"))
+        };
+        let message = "hidden in the pixels ".repeat(20);
+        let png = encode_message_lsb(&source, message.clone(), &options, &key).unwrap();
+        save_to_file(path.clone(), png).unwrap();
+
+        let decode = DecodeOptions {
+            passphrase: Some("pw"),
+            ..DecodeOptions::default()
+        };
+        let decoded = decode_message_lsb(&path, &decode, &key);
+        let without_passphrase = decode_message_lsb(&path, &DecodeOptions::default(), &key);
+        let chunk_types: Vec<String> = list_chunks(&path)
+            .unwrap()
+            .iter()
+            .map(|chunk| chunk.chunk_type.clone())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(decoded.unwrap(), message.as_bytes());
+        assert!(matches!(without_passphrase, Err(Error::PassphraseRequired)));
+        // Only the image data is rewritten; the other chunks stay in place.
+        let original: Vec<String> = list_chunks(&source)
+            .unwrap()
+            .iter()
+            .map(|chunk| chunk.chunk_type.clone())
+            .collect();
+        assert_eq!(chunk_types, original);
+    }
+
+    #[test]
+    fn test_lsb_capacity_includes_overhead() {
+        let source = PathBuf::from("./ferris.png");
+        let lsb_options = LsbOptions::default();
+        let capacity = lsb_capacity(&source, &lsb_options).unwrap();
+        let message = "x".repeat(capacity);
+
+        assert!(encode_message_lsb(
+            &source,
+            message.clone(),
+            &encode_options(None),
+            &lsb_options
+        )
+        .is_ok());
+        assert!(matches!(
+            encode_message_lsb(
+                &source,
+                format!("{}x", message),
+                &encode_options(None),
+                &lsb_options
+            ),
+            Err(Error::CapacityExceeded { .. })
+        ));
+        // The encryption header and tag take room from the message.
+        assert!(matches!(
+            encode_message_lsb(&source, message, &encode_options(Some("pw")), &lsb_options),
+            Err(Error::CapacityExceeded { .. })
+        ));
+    }
 }
@@ -0,0 +1,221 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::{Error, Result};
+
+/// Marks a compressed payload.
+const MAGIC: [u8; 3] = *b"PMz";
+const VERSION: u8 = 1;
+/// `magic | version | algorithm | inflated size (u64)`
+const HEADER_LEN: usize = MAGIC.len() + 2 + 8;
+
+/// Decompressed payloads larger than this are rejected when no limit is given.
+pub const DEFAULT_MAX_INFLATED_SIZE: usize = 64 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zlib,
+    /// Only available with the `zstd` feature.
+    Zstd,
+}
+
+impl Compression {
+    fn id(self) -> u8 {
+        match self {
+            Compression::Zlib => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Compression> {
+        match id {
+            1 => Ok(Compression::Zlib),
+            2 => Ok(Compression::Zstd),
+            _ => Err(Error::InvalidPayload(
+                "algoritmo de compressao desconhecido",
+            )),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "zlib" | "deflate" => Ok(Compression::Zlib),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(Error::InvalidPayload(
+                "algoritmo de compressao desconhecido",
+            )),
+        }
+    }
+}
+
+/// Returns true if `data` starts with the header written by `compress`.
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+pub fn compress(algorithm: Compression, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut compressed = Vec::with_capacity(HEADER_LEN + payload.len() / 2);
+    compressed.extend_from_slice(&MAGIC);
+    compressed.push(VERSION);
+    compressed.push(algorithm.id());
+    compressed.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+
+    match algorithm {
+        Compression::Zlib => {
+            compressed.extend(deflate(payload)?);
+            Ok(compressed)
+        }
+        Compression::Zstd => zstd_compress(compressed, payload),
+    }
+}
+
+/// A bare zlib stream, as used by the zTXt and iTXt chunks.
+pub fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Inflates a bare zlib stream, failing once the output would exceed `max_size` bytes.
+pub fn inflate(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    read_limited(ZlibDecoder::new(data), max_size)
+}
+
+/// Reads a decoder to the end, growing the output as data comes rather than by a size
+/// read from the input, and failing once it would exceed `max_size` bytes.
+fn read_limited(decoder: impl Read, max_size: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    decoder
+        .take((max_size as u64).saturating_add(1))
+        .read_to_end(&mut output)
+        .map_err(|_| Error::InvalidPayload("conteudo comprimido corrompido"))?;
+    if output.len() > max_size {
+        return Err(Error::DecompressedTooLarge { limit: max_size });
+    }
+    Ok(output)
+}
+
+/// Inflates data produced by `compress`, refusing to produce more than `max_size` bytes
+/// whatever the header claims.
+pub fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    if !is_compressed(data) || data.len() < HEADER_LEN {
+        return Err(Error::InvalidPayload("conteudo comprimido incompleto"));
+    }
+    let version = data[MAGIC.len()];
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    let algorithm = Compression::from_id(data[MAGIC.len() + 1])?;
+    let size = u64::from_be_bytes(data[MAGIC.len() + 2..HEADER_LEN].try_into().unwrap());
+    if size > max_size as u64 {
+        return Err(Error::DecompressedTooLarge { limit: max_size });
+    }
+
+    let body = &data[HEADER_LEN..];
+    let payload = match algorithm {
+        Compression::Zlib => inflate(body, max_size)?,
+        Compression::Zstd => read_limited(zstd_decoder(body)?, max_size)?,
+    };
+    if payload.len() as u64 != size {
+        return Err(Error::InvalidPayload("tamanho descomprimido nao confere"));
+    }
+    Ok(payload)
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_compress(mut compressed: Vec<u8>, payload: &[u8]) -> Result<Vec<u8>> {
+    compressed.extend(zstd::encode_all(payload, 19)?);
+    Ok(compressed)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_compress(_compressed: Vec<u8>, _payload: &[u8]) -> Result<Vec<u8>> {
+    Err(Error::InvalidPayload(
+        "suporte a zstd nao foi compilado (feature \"zstd\")",
+    ))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decoder(body: &[u8]) -> Result<Box<dyn Read + '_>> {
+    Ok(Box::new(zstd::Decoder::new(body)?))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decoder(_body: &[u8]) -> Result<Box<dyn Read + '_>> {
+    Err(Error::InvalidPayload(
+        "suporte a zstd nao foi compilado (feature \"zstd\")",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_payload() -> Vec<u8> {
+        "{\"level\":\"info\",\"message\":\"repeated log line\"}\n"
+            .repeat(200)
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_zlib_round_trip() {
+        let payload = testing_payload();
+        let compressed = compress(Compression::Zlib, &payload).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < payload.len() / 10);
+        assert_eq!(decompress(&compressed, payload.len()).unwrap(), payload);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_round_trip() {
+        let payload = testing_payload();
+        let compressed = compress(Compression::Zstd, &payload).unwrap();
+        assert_eq!(decompress(&compressed, payload.len()).unwrap(), payload);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_header_size_is_not_trusted() {
+        let mut compressed = compress(Compression::Zstd, b"hey").unwrap();
+        compressed[MAGIC.len() + 2..HEADER_LEN].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(matches!(
+            decompress(&compressed, usize::MAX),
+            Err(Error::InvalidPayload(_))
+        ));
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        let payload = vec![0u8; 1 << 20];
+        let compressed = compress(Compression::Zlib, &payload).unwrap();
+        assert!(matches!(
+            decompress(&compressed, 1000),
+            Err(Error::DecompressedTooLarge { limit: 1000 })
+        ));
+
+        // A header that understates the size must not get past the limit either.
+        let mut lying = compressed.clone();
+        lying[MAGIC.len() + 2..HEADER_LEN].copy_from_slice(&10u64.to_be_bytes());
+        assert!(matches!(
+            decompress(&lying, 1000),
+            Err(Error::DecompressedTooLarge { limit: 1000 })
+        ));
+
+        let zlib = deflate(b"hey").unwrap();
+        assert_eq!(inflate(&zlib, usize::MAX).unwrap(), b"hey");
+    }
+
+    #[test]
+    fn test_corrupted_stream() {
+        let mut compressed = compress(Compression::Zlib, &testing_payload()).unwrap();
+        compressed.truncate(HEADER_LEN + 10);
+        assert!(decompress(&compressed, DEFAULT_MAX_INFLATED_SIZE).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::keys::{Identity, Recipient};
+use crate::{Error, Result};
+
+/// Marks chunk data sealed with a passphrase.
+const MAGIC: [u8; 3] = *b"PMe";
+/// Argon2id derives the key from the passphrase and ChaCha20-Poly1305 encrypts the message,
+/// with the header passed as associated data so its tag covers the header too.
+const VERSION: u8 = 2;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+/// Marks chunk data sealed to a list of X25519 recipients.
+const RECIPIENTS_MAGIC: [u8; 3] = *b"PMr";
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+/// Ephemeral public key followed by the wrapped file key.
+const STANZA_LEN: usize = 32 + KEY_LEN + TAG_LEN;
+const WRAP_INFO: &[u8] = b"pngme-x25519-v1";
+
+/// Returns true if `data` starts with the header written by `seal`.
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Encrypts `plaintext` with a key derived from `passphrase`.
+///
+/// The output is `magic | version | salt | nonce | ciphertext`, where the
+/// ciphertext carries the Poly1305 tag, which also covers the header.
+pub fn seal(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut sealed = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN);
+    sealed.extend_from_slice(&MAGIC);
+    sealed.push(VERSION);
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &sealed,
+            },
+        )
+        .map_err(|_| Error::InvalidPayload("falha ao cifrar a mensagem"))?;
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Authenticates and decrypts data produced by `seal`.
+pub fn open(passphrase: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+    if !is_sealed(sealed) || sealed.len() < HEADER_LEN {
+        return Err(Error::InvalidPayload("mensagem cifrada incompleta"));
+    }
+    let version = sealed[MAGIC.len()];
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let salt = &sealed[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+    let nonce = &sealed[HEADER_LEN - NONCE_LEN..HEADER_LEN];
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: &sealed[HEADER_LEN..],
+                aad: &sealed[..HEADER_LEN],
+            },
+        )
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+/// Returns true if `data` starts with the header written by `seal_to_recipients`.
+pub fn is_sealed_to_recipients(data: &[u8]) -> bool {
+    data.starts_with(&RECIPIENTS_MAGIC)
+}
+
+/// Encrypts `plaintext` so that any of `recipients` can decrypt it.
+///
+/// A random file key encrypts the message, and is wrapped once per recipient
+/// with a key agreed between a fresh ephemeral X25519 key and the recipient.
+/// The output is `magic | version | count | stanzas | nonce | ciphertext`, and
+/// everything before the ciphertext is authenticated along with it.
+pub fn seal_to_recipients(recipients: &[Recipient], plaintext: &[u8]) -> Result<Vec<u8>> {
+    if recipients.is_empty() || recipients.len() > u8::MAX as usize {
+        return Err(Error::InvalidPayload("numero de destinatarios invalido"));
+    }
+
+    let mut file_key = Key::default();
+    OsRng.fill_bytes(&mut file_key);
+
+    let mut sealed = Vec::new();
+    sealed.extend_from_slice(&RECIPIENTS_MAGIC);
+    sealed.push(VERSION);
+    sealed.push(recipients.len() as u8);
+
+    for recipient in recipients {
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        let shared = ephemeral.diffie_hellman(recipient.public());
+        let wrap_key = derive_wrap_key(shared.as_bytes(), &ephemeral_public, recipient.public());
+
+        let wrapped = ChaCha20Poly1305::new(&wrap_key)
+            .encrypt(&Nonce::default(), file_key.as_slice())
+            .map_err(|_| Error::InvalidPayload("falha ao cifrar a chave"))?;
+        sealed.extend_from_slice(ephemeral_public.as_bytes());
+        sealed.extend_from_slice(&wrapped);
+    }
+
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    sealed.extend_from_slice(&nonce);
+    let ciphertext = ChaCha20Poly1305::new(&file_key)
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &sealed,
+            },
+        )
+        .map_err(|_| Error::InvalidPayload("falha ao cifrar a mensagem"))?;
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypts data produced by `seal_to_recipients` with the secret key of one of its recipients.
+pub fn open_with_identity(identity: &Identity, sealed: &[u8]) -> Result<Vec<u8>> {
+    let header_len = RECIPIENTS_MAGIC.len() + 2;
+    if !is_sealed_to_recipients(sealed) || sealed.len() < header_len {
+        return Err(Error::InvalidPayload("mensagem cifrada incompleta"));
+    }
+    let version = sealed[RECIPIENTS_MAGIC.len()];
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let count = sealed[header_len - 1] as usize;
+    let body_start = header_len + count * STANZA_LEN;
+    if sealed.len() < body_start + NONCE_LEN {
+        return Err(Error::InvalidPayload("mensagem cifrada incompleta"));
+    }
+
+    let recipient = identity.recipient();
+    let file_key = sealed[header_len..body_start]
+        .chunks(STANZA_LEN)
+        .find_map(|stanza| {
+            let ephemeral_public = PublicKey::from(<[u8; 32]>::try_from(&stanza[..32]).unwrap());
+            let shared = identity.secret().diffie_hellman(&ephemeral_public);
+            let wrap_key =
+                derive_wrap_key(shared.as_bytes(), &ephemeral_public, recipient.public());
+            ChaCha20Poly1305::new(&wrap_key)
+                .decrypt(&Nonce::default(), &stanza[32..])
+                .ok()
+        })
+        .ok_or(Error::DecryptionFailed)?;
+
+    let (header, ciphertext) = sealed.split_at(body_start + NONCE_LEN);
+    ChaCha20Poly1305::new(Key::from_slice(&file_key))
+        .decrypt(
+            Nonce::from_slice(&header[body_start..]),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+/// HKDF-SHA256 over the shared secret, bound to both public keys of the exchange.
+fn derive_wrap_key(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> Key {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral.as_bytes());
+    salt[32..].copy_from_slice(recipient.as_bytes());
+
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// Argon2id with fixed parameters, so the same passphrase and salt always derive the same key.
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let params = Params::new(19 * 1024, 2, 1, Some(32))
+        .map_err(|_| Error::InvalidPayload("parametros de derivacao invalidos"))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+    let mut key = Key::default();
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| Error::InvalidPayload("falha ao derivar a chave"))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let sealed = seal("correct horse", b"secret message").unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(open("correct horse", &sealed).unwrap(), b"secret message");
+    }
+
+    #[test]
+    fn test_open_wrong_passphrase() {
+        let sealed = seal("correct horse", b"secret message").unwrap();
+        assert!(matches!(
+            open("battery staple", &sealed),
+            Err(Error::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_open_tampered_payload() {
+        let mut sealed = seal("correct horse", b"secret message").unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(matches!(
+            open("correct horse", &sealed),
+            Err(Error::DecryptionFailed)
+        ));
+        assert!(open("correct horse", &sealed[..10]).is_err());
+    }
+
+    #[test]
+    fn test_open_tampered_header() {
+        let sealed = seal("correct horse", b"secret message").unwrap();
+        // Salt and nonce are part of the authenticated header.
+        for index in [MAGIC.len() + 1, HEADER_LEN - 1] {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            assert!(matches!(
+                open("correct horse", &tampered),
+                Err(Error::DecryptionFailed)
+            ));
+        }
+        let mut old_version = sealed.clone();
+        old_version[MAGIC.len()] = 1;
+        assert!(matches!(
+            open("correct horse", &old_version),
+            Err(Error::UnsupportedVersion(1))
+        ));
+    }
+
+    #[test]
+    fn test_seal_to_recipients() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+
+        let sealed =
+            seal_to_recipients(&[alice.recipient(), bob.recipient()], b"for your eyes").unwrap();
+        assert!(is_sealed_to_recipients(&sealed));
+        assert_eq!(
+            open_with_identity(&alice, &sealed).unwrap(),
+            b"for your eyes"
+        );
+        assert_eq!(open_with_identity(&bob, &sealed).unwrap(), b"for your eyes");
+        assert!(matches!(
+            open_with_identity(&eve, &sealed),
+            Err(Error::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_open_with_identity_truncated() {
+        let alice = Identity::generate();
+        let sealed = seal_to_recipients(&[alice.recipient()], b"message").unwrap();
+        for end in 0..sealed.len() {
+            assert!(open_with_identity(&alice, &sealed[..end]).is_err());
+        }
+    }
+}
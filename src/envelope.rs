@@ -0,0 +1,111 @@
+use crate::{Error, Result};
+
+/// Starts the data of every payload pngme stores with an extra layer; plain messages are
+/// stored as is. The first byte can never begin UTF-8 text, so a plain message is never
+/// mistaken for one.
+const MAGIC: [u8; 4] = *b"\x89PMg";
+const VERSION: u8 = 1;
+/// `magic | version | flags`
+pub const HEADER_LEN: usize = MAGIC.len() + 2;
+
+const PIECE: u8 = 1;
+const ENCRYPTED: u8 = 2;
+const COMPRESSED: u8 = 4;
+const ATTACHMENT: u8 = 8;
+
+/// Says what the body of an envelope holds, so nothing is guessed from its first bytes.
+///
+/// The layers nest in this order: a piece holds part of another envelope, whose body
+/// is encrypted, then compressed, around the message or attachment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Envelope {
+    /// The body is one piece of a longer envelope, see `split`.
+    pub piece: bool,
+    /// The body is encrypted, see `crypto`.
+    pub encrypted: bool,
+    /// The body, once decrypted, is compressed, see `compression`.
+    pub compressed: bool,
+    /// The message is a file, see `attachment`.
+    pub attachment: bool,
+}
+
+impl Envelope {
+    /// Prefixes `body` with the envelope header.
+    pub fn wrap(&self, body: &[u8]) -> Vec<u8> {
+        let mut flags = 0;
+        for (set, flag) in [
+            (self.piece, PIECE),
+            (self.encrypted, ENCRYPTED),
+            (self.compressed, COMPRESSED),
+            (self.attachment, ATTACHMENT),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+        let mut data = Vec::with_capacity(HEADER_LEN + body.len());
+        data.extend_from_slice(&MAGIC);
+        data.push(VERSION);
+        data.push(flags);
+        data.extend_from_slice(body);
+        data
+    }
+
+    /// Splits enveloped data into its envelope and body, or returns `None` for data
+    /// without an envelope, which is a plain message.
+    pub fn open(data: &[u8]) -> Result<Option<(Envelope, &[u8])>> {
+        if !data.starts_with(&MAGIC) {
+            return Ok(None);
+        }
+        if data.len() < HEADER_LEN {
+            return Err(Error::InvalidPayload("envelope incompleto"));
+        }
+        let version = data[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let flags = data[MAGIC.len() + 1];
+        if flags & !(PIECE | ENCRYPTED | COMPRESSED | ATTACHMENT) != 0 {
+            return Err(Error::InvalidPayload("envelope com opcoes desconhecidas"));
+        }
+        let envelope = Envelope {
+            piece: flags & PIECE != 0,
+            encrypted: flags & ENCRYPTED != 0,
+            compressed: flags & COMPRESSED != 0,
+            attachment: flags & ATTACHMENT != 0,
+        };
+        Ok(Some((envelope, &data[HEADER_LEN..])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_and_open() {
+        let envelope = Envelope {
+            encrypted: true,
+            attachment: true,
+            ..Envelope::default()
+        };
+        let data = envelope.wrap(b"body");
+        assert_eq!(
+            Envelope::open(&data).unwrap(),
+            Some((envelope, &b"body"[..]))
+        );
+
+        let mut unknown = data.clone();
+        unknown[MAGIC.len() + 1] |= 0x80;
+        assert!(Envelope::open(&unknown).is_err());
+        assert!(Envelope::open(&data[..HEADER_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn test_plain_text_is_not_an_envelope() {
+        // Text that starts like one of the inner headers is still just text.
+        for text in ["PMe is my initials", "PMz", "PMp", "PMf", "PMr", ""] {
+            assert_eq!(Envelope::open(text.as_bytes()).unwrap(), None);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use std::fmt::Display;
+
+use crate::chunk_type::ChunkType;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The input ended before a complete structure could be read.
+    Truncated {
+        offset: usize,
+        needed: usize,
+    },
+    /// A chunk declares a data length above the limit allowed by the spec.
+    InvalidLength {
+        offset: usize,
+        length: u32,
+    },
+    /// The first eight bytes are not the PNG signature.
+    BadSignature,
+    /// The CRC stored in a chunk does not match its type and data.
+    CrcMismatch {
+        chunk_type: ChunkType,
+        offset: usize,
+        expected: u32,
+        actual: u32,
+    },
+    /// The bytes cannot be used as a chunk type.
+    InvalidChunkType(Vec<u8>),
+    /// No chunk with the requested type exists in the file.
+    ChunkNotFound(String),
+    /// The chunk has no data to show.
+    EmptyData,
+    /// The chunk data does not follow the expected payload format.
+    InvalidPayload(&'static str),
+    /// The payload was written by an unknown format version.
+    UnsupportedVersion(u8),
+    /// The payload could not be authenticated with the given key.
+    DecryptionFailed,
+    /// The message is encrypted and no passphrase was given.
+    PassphraseRequired,
+    /// The message is encrypted to recipients and no identity was given.
+    IdentityRequired,
+    /// A key file or key string could not be parsed.
+    InvalidKey(&'static str),
+    /// No signature chunk covers the requested chunk.
+    SignatureNotFound(String),
+    /// The signature does not match the chunk type and data.
+    SignatureInvalid,
+    /// The signature is valid but was made by a key other than the trusted one.
+    UntrustedSigner(String),
+    /// A piece of a split payload is missing.
+    MissingPiece(u32),
+    /// A piece of a split payload appears more than once.
+    DuplicatePiece(u32),
+    /// The chunk data is not valid UTF-8.
+    InvalidUtf8(std::str::Utf8Error),
+    /// A text chunk keyword breaks the rules of the PNG spec.
+    InvalidKeyword(String),
+    /// The IHDR chunk is missing fields or describes an impossible image.
+    InvalidHeader(&'static str),
+    /// The file breaks the chunk layout rules of the PNG spec.
+    ValidationFailed(usize),
+    /// A chunk cannot be inserted at the requested position.
+    InvalidPosition(String),
+    /// The IDAT stream cannot be turned back into pixels.
+    InvalidImageData(&'static str),
+    /// Storing a message under a critical chunk type would make viewers reject the image.
+    CriticalChunkType(ChunkType),
+    /// The image cannot hide a payload in its pixels.
+    UnsupportedCarrier(&'static str),
+    /// The payload is larger than what the pixels can hide.
+    CapacityExceeded {
+        needed: usize,
+        capacity: usize,
+    },
+    /// A compressed payload inflates to more than the allowed size.
+    DecompressedTooLarge {
+        limit: usize,
+    },
+    Io(std::io::Error),
+}
+
+/// Broad classes of failure, each with its own process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// A chunk type, key, keyword or position given by the user is unusable.
+    InvalidInput,
+    /// The requested chunk or signature is not in the file.
+    NotFound,
+    /// The file is not a well-formed PNG stream.
+    CorruptFile,
+    /// The file parses but breaks the rules of the PNG spec.
+    InvalidPng,
+    /// A stored message cannot be decoded.
+    InvalidPayload,
+    /// A message cannot be decrypted with what was given.
+    Decryption,
+    /// A signature or seal does not check out.
+    Signature,
+    Io,
+}
+
+impl ErrorCategory {
+    /// Stable name used in machine-readable output.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCategory::InvalidInput => "invalid_input",
+            ErrorCategory::NotFound => "not_found",
+            ErrorCategory::CorruptFile => "corrupt_file",
+            ErrorCategory::InvalidPng => "invalid_png",
+            ErrorCategory::InvalidPayload => "invalid_payload",
+            ErrorCategory::Decryption => "decryption",
+            ErrorCategory::Signature => "signature",
+            ErrorCategory::Io => "io",
+        }
+    }
+
+    /// Process exit code; 1 is left to command line usage errors.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCategory::InvalidInput => 2,
+            ErrorCategory::NotFound => 3,
+            ErrorCategory::CorruptFile => 4,
+            ErrorCategory::InvalidPng => 5,
+            ErrorCategory::InvalidPayload => 6,
+            ErrorCategory::Decryption => 7,
+            ErrorCategory::Signature => 8,
+            ErrorCategory::Io => 9,
+        }
+    }
+}
+
+impl Error {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::InvalidChunkType(_)
+            | Error::InvalidKey(_)
+            | Error::InvalidKeyword(_)
+            | Error::InvalidPosition(_)
+            | Error::CriticalChunkType(_)
+            | Error::UnsupportedCarrier(_)
+            | Error::CapacityExceeded { .. } => ErrorCategory::InvalidInput,
+            Error::ChunkNotFound(_) | Error::SignatureNotFound(_) | Error::EmptyData => {
+                ErrorCategory::NotFound
+            }
+            Error::Truncated { .. }
+            | Error::InvalidLength { .. }
+            | Error::BadSignature
+            | Error::CrcMismatch { .. }
+            | Error::InvalidImageData(_) => ErrorCategory::CorruptFile,
+            Error::InvalidHeader(_) | Error::ValidationFailed(_) => ErrorCategory::InvalidPng,
+            Error::InvalidPayload(_)
+            | Error::UnsupportedVersion(_)
+            | Error::MissingPiece(_)
+            | Error::DuplicatePiece(_)
+            | Error::InvalidUtf8(_)
+            | Error::DecompressedTooLarge { .. } => ErrorCategory::InvalidPayload,
+            Error::DecryptionFailed | Error::PassphraseRequired | Error::IdentityRequired => {
+                ErrorCategory::Decryption
+            }
+            Error::SignatureInvalid | Error::UntrustedSigner(_) => ErrorCategory::Signature,
+            Error::Io(_) => ErrorCategory::Io,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Truncated { offset, needed } => write!(
+                f,
+                "Bytes insuficientes no offset {} (necessarios {})",
+                offset, needed
+            ),
+            Error::InvalidLength { offset, length } => write!(
+                f,
+                "Tamanho de chunk invalido no offset {}: {}",
+                offset, length
+            ),
+            Error::BadSignature => write!(f, "Cabecalho incorreto"),
+            Error::CrcMismatch {
+                chunk_type,
+                offset,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Checksum incorreta no chunk {} (offset {}): esperada {:#010x}, encontrada {:#010x}",
+                chunk_type, offset, expected, actual
+            ),
+            Error::InvalidChunkType(bytes) => {
+                write!(f, "Tipo de chunk invalido: {:?}", bytes)
+            }
+            Error::ChunkNotFound(chunk_type) => {
+                write!(f, "Chunk nao encontrado: {}", chunk_type)
+            }
+            Error::EmptyData => write!(f, "Sem dados para mostrar."),
+            Error::InvalidPayload(reason) => write!(f, "Conteudo invalido: {}", reason),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "Versao de formato nao suportada: {}", version)
+            }
+            Error::DecryptionFailed => {
+                write!(f, "Nao foi possivel decifrar a mensagem (senha incorreta?)")
+            }
+            Error::PassphraseRequired => write!(f, "A mensagem esta cifrada, informe a senha"),
+            Error::IdentityRequired => write!(
+                f,
+                "A mensagem esta cifrada para destinatarios, informe a identidade"
+            ),
+            Error::InvalidKey(reason) => write!(f, "Chave invalida: {}", reason),
+            Error::SignatureNotFound(chunk_type) => {
+                write!(f, "Nenhuma assinatura encontrada para o chunk {}", chunk_type)
+            }
+            Error::SignatureInvalid => write!(f, "Assinatura invalida"),
+            Error::UntrustedSigner(fingerprint) => write!(
+                f,
+                "Assinatura valida, mas feita por uma chave nao confiavel ({})",
+                fingerprint
+            ),
+            Error::MissingPiece(sequence) => {
+                write!(f, "Pedaco {} da mensagem esta faltando", sequence)
+            }
+            Error::DuplicatePiece(sequence) => {
+                write!(f, "Pedaco {} da mensagem esta duplicado", sequence)
+            }
+            Error::InvalidUtf8(err) => write!(f, "Texto UTF-8 invalido: {}", err),
+            Error::InvalidKeyword(keyword) => write!(f, "Palavra-chave invalida: {:?}", keyword),
+            Error::InvalidHeader(reason) => write!(f, "Cabecalho de imagem invalido: {}", reason),
+            Error::ValidationFailed(count) => {
+                write!(f, "Arquivo PNG invalido: {} problema(s) encontrado(s)", count)
+            }
+            Error::InvalidPosition(reason) => write!(f, "Posicao invalida: {}", reason),
+            Error::InvalidImageData(reason) => write!(f, "Dados de imagem invalidos: {}", reason),
+            Error::CriticalChunkType(chunk_type) => write!(
+                f,
+                "O tipo de chunk {} e critico e faria os leitores recusarem a imagem",
+                chunk_type
+            ),
+            Error::UnsupportedCarrier(reason) => {
+                write!(f, "Imagem nao serve para esconder a mensagem: {}", reason)
+            }
+            Error::CapacityExceeded { needed, capacity } => write!(
+                f,
+                "A mensagem tem {} bytes, mas a imagem so comporta {}",
+                needed, capacity
+            ),
+            Error::DecompressedTooLarge { limit } => write!(
+                f,
+                "Conteudo descomprimido excede o limite de {} bytes",
+                limit
+            ),
+            Error::Io(err) => write!(f, "Erro de E/S: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidUtf8(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CATEGORIES: [ErrorCategory; 8] = [
+        ErrorCategory::InvalidInput,
+        ErrorCategory::NotFound,
+        ErrorCategory::CorruptFile,
+        ErrorCategory::InvalidPng,
+        ErrorCategory::InvalidPayload,
+        ErrorCategory::Decryption,
+        ErrorCategory::Signature,
+        ErrorCategory::Io,
+    ];
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let mut codes: Vec<i32> = CATEGORIES.iter().map(|c| c.exit_code()).collect();
+        codes.sort_unstable();
+        assert_eq!(codes, (2..=9).collect::<Vec<_>>());
+
+        let mut names: Vec<&str> = CATEGORIES.iter().map(|c| c.name()).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), CATEGORIES.len());
+    }
+
+    #[test]
+    fn test_error_categories() {
+        let cases = [
+            (
+                Error::InvalidChunkType(b"ru1t".to_vec()),
+                ErrorCategory::InvalidInput,
+                2,
+            ),
+            (
+                Error::ChunkNotFound("ruSt".to_string()),
+                ErrorCategory::NotFound,
+                3,
+            ),
+            (Error::BadSignature, ErrorCategory::CorruptFile, 4),
+            (Error::ValidationFailed(1), ErrorCategory::InvalidPng, 5),
+            (Error::MissingPiece(0), ErrorCategory::InvalidPayload, 6),
+            (Error::PassphraseRequired, ErrorCategory::Decryption, 7),
+            (Error::SignatureInvalid, ErrorCategory::Signature, 8),
+            (
+                Error::Io(std::io::Error::from(std::io::ErrorKind::NotFound)),
+                ErrorCategory::Io,
+                9,
+            ),
+        ];
+        for (error, category, code) in cases {
+            assert_eq!(error.category(), category, "{}", error);
+            assert_eq!(error.category().exit_code(), code);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{Error, Result};
+
+/// The scanline filters of PNG filter method 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl FilterType {
+    pub fn from_u8(value: u8) -> Option<FilterType> {
+        match value {
+            0 => Some(FilterType::None),
+            1 => Some(FilterType::Sub),
+            2 => Some(FilterType::Up),
+            3 => Some(FilterType::Average),
+            4 => Some(FilterType::Paeth),
+            _ => None,
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            FilterType::None => 0,
+            FilterType::Sub => 1,
+            FilterType::Up => 2,
+            FilterType::Average => 3,
+            FilterType::Paeth => 4,
+        }
+    }
+}
+
+/// How `filter_rows` picks the filter of each scanline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterStrategy {
+    /// The same filter on every row.
+    Fixed(FilterType),
+    /// Per row, the filter whose output has the smallest sum of absolute values
+    /// (read as signed bytes), the heuristic recommended by the spec.
+    #[default]
+    Adaptive,
+}
+
+const ALL_FILTERS: [FilterType; 5] = [
+    FilterType::None,
+    FilterType::Sub,
+    FilterType::Up,
+    FilterType::Average,
+    FilterType::Paeth,
+];
+
+/// Bytes between a byte and the one it is predicted from: a whole pixel, or 1 below 8 bits per pixel.
+pub fn filter_distance(bits_per_pixel: usize) -> usize {
+    bits_per_pixel.div_ceil(8)
+}
+
+/// Undoes the filter on each scanline of `data`, where every row is a filter type byte
+/// followed by `row_bytes` bytes, and returns the rows without their filter bytes.
+pub fn unfilter_rows(
+    data: &[u8],
+    row_bytes: usize,
+    height: usize,
+    bits_per_pixel: usize,
+) -> Result<Vec<u8>> {
+    if data.len() < (row_bytes + 1) * height {
+        return Err(Error::InvalidImageData("dados de imagem incompletos"));
+    }
+    let distance = filter_distance(bits_per_pixel);
+    let mut pixels = vec![0u8; row_bytes * height];
+    let mut previous = vec![0u8; row_bytes];
+    for (y, scanline) in data.chunks_exact(row_bytes + 1).take(height).enumerate() {
+        let filter = FilterType::from_u8(scanline[0])
+            .ok_or(Error::InvalidImageData("tipo de filtro desconhecido"))?;
+        let row = &mut pixels[y * row_bytes..(y + 1) * row_bytes];
+        row.copy_from_slice(&scanline[1..]);
+        unfilter(filter, distance, &previous, row);
+        previous.copy_from_slice(row);
+    }
+    Ok(pixels)
+}
+
+/// The inverse of `unfilter_rows`: prefixes each of the `height` rows of `pixels` with the
+/// type of the filter chosen for it, followed by the filtered bytes.
+pub fn filter_rows(
+    pixels: &[u8],
+    row_bytes: usize,
+    bits_per_pixel: usize,
+    strategy: FilterStrategy,
+) -> Vec<u8> {
+    let distance = filter_distance(bits_per_pixel);
+    let mut filtered = Vec::with_capacity(pixels.len() + pixels.len() / row_bytes.max(1));
+    let mut previous = vec![0u8; row_bytes];
+    let mut candidate = vec![0u8; row_bytes];
+    let mut best = vec![0u8; row_bytes];
+    for row in pixels.chunks_exact(row_bytes) {
+        let chosen = match strategy {
+            FilterStrategy::Fixed(filter_type) => {
+                filter(filter_type, distance, &previous, row, &mut best);
+                filter_type
+            }
+            FilterStrategy::Adaptive => {
+                let mut chosen = (FilterType::None, u64::MAX);
+                for filter_type in ALL_FILTERS {
+                    filter(filter_type, distance, &previous, row, &mut candidate);
+                    let score = candidate
+                        .iter()
+                        .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                        .sum();
+                    if score < chosen.1 {
+                        chosen = (filter_type, score);
+                        std::mem::swap(&mut best, &mut candidate);
+                    }
+                }
+                chosen.0
+            }
+        };
+        filtered.push(chosen.to_u8());
+        filtered.extend_from_slice(&best);
+        previous.copy_from_slice(row);
+    }
+    filtered
+}
+
+/// Writes `row` filtered with `filter_type` into `out`; `previous` is the unfiltered row above.
+pub fn filter(
+    filter_type: FilterType,
+    distance: usize,
+    previous: &[u8],
+    row: &[u8],
+    out: &mut [u8],
+) {
+    for i in 0..row.len() {
+        let left = if i >= distance { row[i - distance] } else { 0 };
+        let upper_left = if i >= distance {
+            previous[i - distance]
+        } else {
+            0
+        };
+        let prediction = match filter_type {
+            FilterType::None => 0,
+            FilterType::Sub => left,
+            FilterType::Up => previous[i],
+            FilterType::Average => ((left as u16 + previous[i] as u16) / 2) as u8,
+            FilterType::Paeth => paeth_predictor(left, previous[i], upper_left),
+        };
+        out[i] = row[i].wrapping_sub(prediction);
+    }
+}
+
+/// Reconstructs `row` in place; `previous` is the reconstructed row above, all zero for the first one.
+pub fn unfilter(filter: FilterType, distance: usize, previous: &[u8], row: &mut [u8]) {
+    match filter {
+        FilterType::None => {}
+        FilterType::Sub => {
+            for i in distance..row.len() {
+                row[i] = row[i].wrapping_add(row[i - distance]);
+            }
+        }
+        FilterType::Up => {
+            for (byte, above) in row.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(*above);
+            }
+        }
+        FilterType::Average => {
+            for i in 0..row.len() {
+                let left = if i >= distance { row[i - distance] } else { 0 };
+                let average = (left as u16 + previous[i] as u16) / 2;
+                row[i] = row[i].wrapping_add(average as u8);
+            }
+        }
+        FilterType::Paeth => {
+            for i in 0..row.len() {
+                let (left, upper_left) = if i >= distance {
+                    (row[i - distance], previous[i - distance])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth_predictor(left, previous[i], upper_left));
+            }
+        }
+    }
+}
+
+/// Picks whichever of left, above and upper left is closest to `left + above - upper_left`.
+fn paeth_predictor(left: u8, above: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_above = (estimate - above as i16).abs();
+    let distance_upper_left = (estimate - upper_left as i16).abs();
+    if distance_left <= distance_above && distance_left <= distance_upper_left {
+        left
+    } else if distance_above <= distance_upper_left {
+        above
+    } else {
+        upper_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paeth_predictor() {
+        assert_eq!(paeth_predictor(10, 20, 10), 20);
+        assert_eq!(paeth_predictor(20, 10, 10), 20);
+        assert_eq!(paeth_predictor(10, 10, 20), 10);
+        assert_eq!(paeth_predictor(0, 0, 0), 0);
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let pixels: Vec<u8> = (0..60u8).map(|i| i.wrapping_mul(37) ^ (i / 6)).collect();
+        let mut strategies: Vec<FilterStrategy> =
+            ALL_FILTERS.into_iter().map(FilterStrategy::Fixed).collect();
+        strategies.push(FilterStrategy::Adaptive);
+        for strategy in strategies {
+            for bits_per_pixel in [1, 8, 24, 64] {
+                let filtered = filter_rows(&pixels, 12, bits_per_pixel, strategy);
+                assert_eq!(filtered.len(), 5 * 13);
+                assert_eq!(
+                    unfilter_rows(&filtered, 12, 5, bits_per_pixel).unwrap(),
+                    pixels
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_adaptive_filter_picks_cheapest() {
+        // A horizontal gradient: Sub turns every row into small constant deltas.
+        let pixels: Vec<u8> = (0..4).flat_map(|_| (0..16u8).map(|x| x * 10)).collect();
+        let filtered = filter_rows(&pixels, 16, 8, FilterStrategy::Adaptive);
+        assert_eq!(filtered[0], FilterType::Sub.to_u8());
+        assert!(filtered[1..17].iter().skip(1).all(|&byte| byte == 10));
+    }
+
+    #[test]
+    fn test_unfilter_rows() {
+        // Two rows of three 8-bit grayscale pixels: Sub, then Up.
+        let data = [1, 5, 1, 1, 2, 1, 1, 1];
+        let pixels = unfilter_rows(&data, 3, 2, 8).unwrap();
+        assert_eq!(pixels, [5, 6, 7, 6, 7, 8]);
+
+        let bad_filter = [7, 0, 0, 0];
+        assert!(matches!(
+            unfilter_rows(&bad_filter, 3, 1, 8),
+            Err(Error::InvalidImageData(_))
+        ));
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use crate::chunk_type::ChunkType;
+
+const BYTES_PER_LINE: usize = 16;
+/// Width of the hex column: two digits and a space per byte, plus the gap after the eighth.
+const HEX_WIDTH: usize = BYTES_PER_LINE * 3 + 1;
+
+/// Classic hex dump of `bytes`, 16 per line, with offsets counted from `offset`.
+pub fn hexdump(bytes: &[u8], offset: usize) -> String {
+    let mut out = String::new();
+    for (line, row) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        dump_line(
+            &mut out,
+            offset + line * BYTES_PER_LINE,
+            row,
+            &format!("|{}|", printable(row)),
+        );
+    }
+    out
+}
+
+/// Hex dump of one chunk with the length, type, data and CRC fields labelled.
+pub(crate) fn annotated_chunk(
+    offset: usize,
+    chunk_type: &ChunkType,
+    data: &[u8],
+    crc: u32,
+) -> String {
+    let mut out = String::new();
+    let length = data.len() as u32;
+    dump_line(
+        &mut out,
+        offset,
+        &length.to_be_bytes(),
+        &format!("length {}", length),
+    );
+    dump_line(
+        &mut out,
+        offset + 4,
+        &chunk_type.bytes(),
+        &format!("type   {}", chunk_type),
+    );
+    for (line, row) in data.chunks(BYTES_PER_LINE).enumerate() {
+        let label = if line == 0 { "data" } else { "" };
+        dump_line(
+            &mut out,
+            offset + 8 + line * BYTES_PER_LINE,
+            row,
+            &format!("{:6} |{}|", label, printable(row)),
+        );
+    }
+    dump_line(
+        &mut out,
+        offset + 8 + data.len(),
+        &crc.to_be_bytes(),
+        &format!("crc    {:#010x}", crc),
+    );
+    out
+}
+
+fn dump_line(out: &mut String, offset: usize, row: &[u8], annotation: &str) {
+    let mut hex = String::with_capacity(HEX_WIDTH);
+    for (i, byte) in row.iter().enumerate() {
+        if i == BYTES_PER_LINE / 2 {
+            hex.push(' ');
+        }
+        write!(hex, "{:02x} ", byte).unwrap();
+    }
+    writeln!(out, "{:08x}  {:<HEX_WIDTH$} {}", offset, hex, annotation).unwrap();
+}
+
+/// Printable ASCII as itself, everything else as a dot.
+fn printable(row: &[u8]) -> String {
+    row.iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_hexdump_lines() {
+        let dump = hexdump(b"0123456789abcdefXY\n", 0x20);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("00000020  30 31 32 33 34 35 36 37  38 39"));
+        assert!(lines[0].ends_with("|0123456789abcdef|"));
+        assert!(lines[1].starts_with("00000030  58 59 0a "));
+        assert!(lines[1].ends_with("|XY.|"));
+    }
+
+    #[test]
+    fn test_annotated_chunk() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let dump = annotated_chunk(8, &chunk_type, &[0u8; 20], 0xdeadbeef);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("00000008  00 00 00 14 "));
+        assert!(lines[0].ends_with("length 20"));
+        assert!(lines[1].starts_with("0000000c  72 75 53 74 "));
+        assert!(lines[1].ends_with("type   ruSt"));
+        assert!(lines[2].starts_with("00000010  ") && lines[2].contains(" data "));
+        assert!(lines[3].starts_with("00000020  00 00 00 00 "));
+        assert!(lines[4].starts_with("00000024  de ad be ef "));
+        assert!(lines[4].ends_with("crc    0xdeadbeef"));
+    }
+}
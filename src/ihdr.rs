@@ -0,0 +1,263 @@
+use std::fmt::Display;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::{Error, Result};
+
+const IHDR_LENGTH: usize = 13;
+/// Largest width or height allowed by the PNG specification (2^31 - 1).
+const MAX_DIMENSION: u32 = 0x7fff_ffff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn from_u8(value: u8) -> Option<ColorType> {
+        match value {
+            0 => Some(ColorType::Grayscale),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Indexed),
+            4 => Some(ColorType::GrayscaleAlpha),
+            6 => Some(ColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// Samples per pixel.
+    pub fn channels(self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Bit depths the spec allows for this color type.
+    pub fn allowed_bit_depths(self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale + alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The image header: dimensions and pixel format of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    /// Always 0 (deflate) in valid files.
+    pub compression_method: u8,
+    /// Always 0 (adaptive filtering) in valid files.
+    pub filter_method: u8,
+    /// 0 for none, 1 for Adam7.
+    pub interlace_method: u8,
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if *chunk.chunk_type() != ChunkType::IHDR {
+            return Err(Error::InvalidHeader("o chunk nao e IHDR"));
+        }
+        Ihdr::from_bytes(chunk.data())
+    }
+}
+
+impl Ihdr {
+    /// A non-interlaced header with the standard compression and filter methods.
+    pub fn new(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Ihdr {
+        Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 0,
+        }
+    }
+
+    /// Parses and validates the 13 data bytes of an IHDR chunk.
+    pub fn from_bytes(data: &[u8]) -> Result<Ihdr> {
+        if data.len() != IHDR_LENGTH {
+            return Err(Error::InvalidHeader("IHDR deve ter 13 bytes"));
+        }
+        let header = Ihdr {
+            width: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            bit_depth: data[8],
+            color_type: ColorType::from_u8(data[9])
+                .ok_or(Error::InvalidHeader("tipo de cor desconhecido"))?,
+            compression_method: data[10],
+            filter_method: data[11],
+            interlace_method: data[12],
+        };
+        header.validate()?;
+        Ok(header)
+    }
+
+    pub fn to_bytes(&self) -> [u8; IHDR_LENGTH] {
+        let mut bytes = [0u8; IHDR_LENGTH];
+        bytes[0..4].copy_from_slice(&self.width.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.height.to_be_bytes());
+        bytes[8] = self.bit_depth;
+        bytes[9] = self.color_type.to_u8();
+        bytes[10] = self.compression_method;
+        bytes[11] = self.filter_method;
+        bytes[12] = self.interlace_method;
+        bytes
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        self.validate()?;
+        Chunk::try_new(ChunkType::IHDR, self.to_bytes().to_vec())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.width > MAX_DIMENSION {
+            return Err(Error::InvalidHeader("largura invalida"));
+        }
+        if self.height == 0 || self.height > MAX_DIMENSION {
+            return Err(Error::InvalidHeader("altura invalida"));
+        }
+        if !self
+            .color_type
+            .allowed_bit_depths()
+            .contains(&self.bit_depth)
+        {
+            return Err(Error::InvalidHeader(
+                "profundidade de bits invalida para o tipo de cor",
+            ));
+        }
+        if self.compression_method != 0 {
+            return Err(Error::InvalidHeader("metodo de compressao desconhecido"));
+        }
+        if self.filter_method != 0 {
+            return Err(Error::InvalidHeader("metodo de filtro desconhecido"));
+        }
+        if self.interlace_method > 1 {
+            return Err(Error::InvalidHeader(
+                "metodo de entrelacamento desconhecido",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn is_interlaced(&self) -> bool {
+        self.interlace_method == 1
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() as usize * self.bit_depth as usize
+    }
+
+    /// Bytes in one unfiltered scanline of `width` pixels, without the filter type byte.
+    pub fn row_bytes(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Dimensions: {}x{}", self.width, self.height)?;
+        writeln!(f, "Bit depth: {}", self.bit_depth)?;
+        writeln!(
+            f,
+            "Color type: {} ({})",
+            self.color_type,
+            self.color_type.to_u8()
+        )?;
+        writeln!(f, "Compression: deflate")?;
+        writeln!(f, "Filter: adaptive")?;
+        write!(
+            f,
+            "Interlace: {}",
+            if self.is_interlaced() {
+                "Adam7"
+            } else {
+                "none"
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ihdr_round_trip() {
+        let header = Ihdr::new(640, 480, 8, ColorType::Rgba);
+        let chunk = header.to_chunk().unwrap();
+        assert_eq!(chunk.length(), 13);
+        assert_eq!(Ihdr::try_from(&chunk).unwrap(), header);
+        assert_eq!(header.row_bytes(640), 2560);
+    }
+
+    #[test]
+    fn test_ihdr_bit_depths() {
+        assert!(Ihdr::new(1, 1, 1, ColorType::Grayscale).validate().is_ok());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Grayscale).validate().is_ok());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Indexed).validate().is_err());
+        assert!(Ihdr::new(1, 1, 4, ColorType::Rgb).validate().is_err());
+        assert!(Ihdr::new(1, 1, 3, ColorType::Grayscale).validate().is_err());
+        assert_eq!(Ihdr::new(3, 1, 1, ColorType::Grayscale).row_bytes(3), 1);
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        let valid = Ihdr::new(2, 2, 8, ColorType::Rgb).to_bytes();
+        assert!(Ihdr::from_bytes(&valid[..12]).is_err());
+
+        let corruptions: [fn(&mut [u8; 13]); 6] = [
+            |bytes| bytes[0..4].fill(0),
+            |bytes| bytes[4..8].fill(0xff),
+            |bytes| bytes[9] = 5,
+            |bytes| bytes[10] = 1,
+            |bytes| bytes[11] = 1,
+            |bytes| bytes[12] = 2,
+        ];
+        for corrupt in corruptions {
+            let mut bytes = valid;
+            corrupt(&mut bytes);
+            assert!(matches!(
+                Ihdr::from_bytes(&bytes),
+                Err(Error::InvalidHeader(_))
+            ));
+        }
+    }
+}
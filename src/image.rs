@@ -0,0 +1,602 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::compression;
+use crate::filter::{self, FilterStrategy};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// `(x start, y start, x step, y step)` of the seven Adam7 passes.
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// IDAT chunk size used by libpng.
+pub const DEFAULT_IDAT_SIZE: usize = 8192;
+
+/// How `encode` turns pixels into IDAT chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderOptions {
+    pub filter: FilterStrategy,
+    /// Largest IDAT chunk to write; the compressed stream is split over as many as it takes.
+    pub max_idat_size: usize,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> Self {
+        EncoderOptions {
+            filter: FilterStrategy::Adaptive,
+            max_idat_size: DEFAULT_IDAT_SIZE,
+        }
+    }
+}
+
+/// One pixel, with every sample widened to 16 bits whatever the bit depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pixel {
+    Gray(u16),
+    GrayAlpha([u16; 2]),
+    Rgb([u16; 3]),
+    Rgba([u16; 4]),
+    /// An index into the palette.
+    Indexed(u8),
+}
+
+/// Decoded pixels: unfiltered, deinterlaced scanlines in the layout the header describes,
+/// each row starting on a byte boundary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    header: Ihdr,
+    palette: Option<Vec<[u8; 3]>>,
+    data: Vec<u8>,
+}
+
+impl TryFrom<&Png> for Image {
+    type Error = Error;
+    fn try_from(png: &Png) -> Result<Self> {
+        let header = png.header()?;
+        let palette = png
+            .chunk_by_type(&ChunkType::PLTE)
+            .map(|chunk| parse_palette(chunk.data()))
+            .transpose()?;
+        let compressed: Vec<u8> = png
+            .chunks_by_type(&ChunkType::IDAT)
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+        decode(header, palette, &compressed)
+    }
+}
+
+impl Image {
+    /// Wraps already unfiltered pixel data; fails if its size does not match the header.
+    pub fn new(header: Ihdr, palette: Option<Vec<[u8; 3]>>, data: Vec<u8>) -> Result<Image> {
+        header.validate()?;
+        if header.color_type == ColorType::Indexed && palette.is_none() {
+            return Err(Error::InvalidImageData("imagem indexada sem paleta"));
+        }
+        if Some(data.len()) != image_size(&header) {
+            return Err(Error::InvalidImageData(
+                "tamanho dos dados nao confere com o cabecalho",
+            ));
+        }
+        Ok(Image {
+            header,
+            palette,
+            data,
+        })
+    }
+
+    pub fn header(&self) -> &Ihdr {
+        &self.header
+    }
+
+    pub fn width(&self) -> u32 {
+        self.header.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.header.height
+    }
+
+    pub fn palette(&self) -> Option<&[[u8; 3]]> {
+        self.palette.as_deref()
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Bytes in one row of pixels.
+    pub fn stride(&self) -> usize {
+        self.header.row_bytes(self.header.width)
+    }
+
+    pub fn row(&self, y: u32) -> &[u8] {
+        let stride = self.stride();
+        &self.data[y as usize * stride..(y as usize + 1) * stride]
+    }
+
+    /// Sample `channel` of the pixel at `(x, y)`. Panics if any of them is out of range.
+    pub fn sample(&self, x: u32, y: u32, channel: usize) -> u16 {
+        let channels = self.header.color_type.channels() as usize;
+        assert!(x < self.width() && y < self.height() && channel < channels);
+        let bit_depth = self.header.bit_depth as usize;
+        let row = self.row(y);
+        let bit = (x as usize * channels + channel) * bit_depth;
+        match bit_depth {
+            16 => u16::from_be_bytes([row[bit / 8], row[bit / 8 + 1]]),
+            8 => row[bit / 8] as u16,
+            _ => {
+                let shift = 8 - bit_depth - bit % 8;
+                ((row[bit / 8] >> shift) & ((1 << bit_depth) - 1)) as u16
+            }
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Pixel {
+        let sample = |channel| self.sample(x, y, channel);
+        match self.header.color_type {
+            ColorType::Grayscale => Pixel::Gray(sample(0)),
+            ColorType::GrayscaleAlpha => Pixel::GrayAlpha([sample(0), sample(1)]),
+            ColorType::Rgb => Pixel::Rgb([sample(0), sample(1), sample(2)]),
+            ColorType::Rgba => Pixel::Rgba([sample(0), sample(1), sample(2), sample(3)]),
+            ColorType::Indexed => Pixel::Indexed(sample(0) as u8),
+        }
+    }
+}
+
+/// Inflates and unfilters the concatenated IDAT data of an image, deinterlacing it if needed.
+pub fn decode(header: Ihdr, palette: Option<Vec<[u8; 3]>>, compressed: &[u8]) -> Result<Image> {
+    header.validate()?;
+    let bits_per_pixel = header.bits_per_pixel();
+    let expected = filtered_size(&header).ok_or(Error::InvalidImageData("imagem grande demais"))?;
+    let raw = compression::inflate(compressed, expected).map_err(|err| match err {
+        Error::DecompressedTooLarge { .. } => Error::InvalidImageData("IDAT maior que o esperado"),
+        _ => Error::InvalidImageData("fluxo zlib corrompido"),
+    })?;
+    // Checked before allocating the output, so a tiny IDAT cannot claim a huge image.
+    if raw.len() < expected {
+        return Err(Error::InvalidImageData("dados de imagem incompletos"));
+    }
+
+    let data = if !header.is_interlaced() {
+        filter::unfilter_rows(
+            &raw,
+            header.row_bytes(header.width),
+            header.height as usize,
+            bits_per_pixel,
+        )?
+    } else {
+        let stride = header.row_bytes(header.width);
+        let mut data = vec![0u8; stride * header.height as usize];
+        let mut offset = 0;
+        for (pass, width, height) in adam7_passes(&header) {
+            let pass_stride = header.row_bytes(width as u32);
+            let pixels =
+                filter::unfilter_rows(&raw[offset..], pass_stride, height, bits_per_pixel)?;
+            offset += (pass_stride + 1) * height;
+
+            let (x_start, y_start, x_step, y_step) = pass;
+            for (row, pass_row) in pixels.chunks_exact(pass_stride).enumerate() {
+                let y = y_start + row * y_step;
+                let image_row = &mut data[y * stride..(y + 1) * stride];
+                for column in 0..width {
+                    let x = x_start + column * x_step;
+                    copy_pixel(pass_row, column, image_row, x, bits_per_pixel);
+                }
+            }
+        }
+        data
+    };
+    Image::new(header, palette, data)
+}
+
+/// Filters, deflates and splits the pixels of `image` into IDAT chunks, and wraps them
+/// with IHDR, PLTE (if there is a palette) and IEND into a new file.
+pub fn encode(image: &Image, options: &EncoderOptions) -> Result<Png> {
+    let header = image.header;
+    if let Some(palette) = &image.palette {
+        let allowed = match header.color_type {
+            ColorType::Indexed => 1 << header.bit_depth,
+            ColorType::Rgb | ColorType::Rgba => 256,
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => 0,
+        };
+        if palette.is_empty() || palette.len() > allowed {
+            return Err(Error::InvalidImageData(
+                "paleta invalida para o tipo de cor",
+            ));
+        }
+    }
+
+    let bits_per_pixel = header.bits_per_pixel();
+    let stride = image.stride();
+    let raw = if !header.is_interlaced() {
+        filter::filter_rows(&image.data, stride, bits_per_pixel, options.filter)
+    } else {
+        let mut raw = Vec::new();
+        for (pass, width, height) in adam7_passes(&header) {
+            let (x_start, y_start, x_step, y_step) = pass;
+            let pass_stride = header.row_bytes(width as u32);
+            let mut pixels = vec![0u8; pass_stride * height];
+            for (row, pass_row) in pixels.chunks_exact_mut(pass_stride).enumerate() {
+                let y = y_start + row * y_step;
+                let image_row = &image.data[y * stride..(y + 1) * stride];
+                for column in 0..width {
+                    let x = x_start + column * x_step;
+                    copy_pixel(image_row, x, pass_row, column, bits_per_pixel);
+                }
+            }
+            raw.extend(filter::filter_rows(
+                &pixels,
+                pass_stride,
+                bits_per_pixel,
+                options.filter,
+            ));
+        }
+        raw
+    };
+    let compressed = compression::deflate(&raw)?;
+
+    let mut chunks = vec![header.to_chunk()?];
+    if let Some(palette) = &image.palette {
+        chunks.push(Chunk::try_new(ChunkType::PLTE, palette.concat())?);
+    }
+    let idat_size = options.max_idat_size.clamp(1, Chunk::MAX_LENGTH as usize);
+    for piece in compressed.chunks(idat_size) {
+        chunks.push(Chunk::try_new(ChunkType::IDAT, piece.to_vec())?);
+    }
+    chunks.push(Chunk::try_new(ChunkType::IEND, Vec::new())?);
+    Ok(Png::from_chunks(chunks))
+}
+
+/// Size of the unfiltered pixel data.
+fn image_size(header: &Ihdr) -> Option<usize> {
+    header
+        .row_bytes(header.width)
+        .checked_mul(header.height as usize)
+}
+
+/// Size of the inflated IDAT stream: every scanline of every pass plus its filter type byte.
+fn filtered_size(header: &Ihdr) -> Option<usize> {
+    if !header.is_interlaced() {
+        return (header.row_bytes(header.width) + 1).checked_mul(header.height as usize);
+    }
+    adam7_passes(header).try_fold(0usize, |total, (_, width, height)| {
+        (header.row_bytes(width as u32) + 1)
+            .checked_mul(height)?
+            .checked_add(total)
+    })
+}
+
+/// The Adam7 passes that hold at least one pixel, with the size of their reduced image.
+fn adam7_passes(
+    header: &Ihdr,
+) -> impl Iterator<Item = ((usize, usize, usize, usize), usize, usize)> + '_ {
+    ADAM7_PASSES.into_iter().filter_map(|pass| {
+        let (width, height) = pass_size(header, pass);
+        (width > 0 && height > 0).then_some((pass, width, height))
+    })
+}
+
+/// Width and height of the reduced image of one Adam7 pass.
+fn pass_size(
+    header: &Ihdr,
+    (x_start, y_start, x_step, y_step): (usize, usize, usize, usize),
+) -> (usize, usize) {
+    let span = |size: usize, start: usize, step: usize| {
+        if size > start {
+            (size - start).div_ceil(step)
+        } else {
+            0
+        }
+    };
+    (
+        span(header.width as usize, x_start, x_step),
+        span(header.height as usize, y_start, y_step),
+    )
+}
+
+/// Copies pixel `from` of `source` to pixel `to` of `target`, bit by bit below 8 bits per pixel.
+fn copy_pixel(source: &[u8], from: usize, target: &mut [u8], to: usize, bits_per_pixel: usize) {
+    if bits_per_pixel >= 8 {
+        let bytes = bits_per_pixel / 8;
+        target[to * bytes..(to + 1) * bytes]
+            .copy_from_slice(&source[from * bytes..(from + 1) * bytes]);
+        return;
+    }
+    let mask = (1u8 << bits_per_pixel) - 1;
+    let from_bit = from * bits_per_pixel;
+    let to_bit = to * bits_per_pixel;
+    let value = (source[from_bit / 8] >> (8 - bits_per_pixel - from_bit % 8)) & mask;
+    let shift = 8 - bits_per_pixel - to_bit % 8;
+    target[to_bit / 8] = (target[to_bit / 8] & !(mask << shift)) | (value << shift);
+}
+
+fn parse_palette(data: &[u8]) -> Result<Vec<[u8; 3]>> {
+    if data.is_empty() || !data.len().is_multiple_of(3) || data.len() > 256 * 3 {
+        return Err(Error::InvalidImageData("paleta com tamanho invalido"));
+    }
+    Ok(data
+        .chunks_exact(3)
+        .map(|entry| [entry[0], entry[1], entry[2]])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterType;
+
+    /// Deterministic filler bytes.
+    fn noise(len: usize, mut state: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn reference_decode(bytes: &[u8]) -> Vec<u8> {
+        let mut decoder = reference_png::Decoder::new(bytes);
+        decoder.set_transformations(reference_png::Transformations::IDENTITY);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        buffer.truncate(info.buffer_size());
+        buffer
+    }
+
+    fn reference_encode(
+        header: &Ihdr,
+        palette: &[u8],
+        data: &[u8],
+        filter: reference_png::FilterType,
+    ) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = reference_png::Encoder::new(&mut bytes, header.width, header.height);
+        encoder.set_color(reference_png::ColorType::from_u8(header.color_type.to_u8()).unwrap());
+        encoder.set_depth(reference_png::BitDepth::from_u8(header.bit_depth).unwrap());
+        encoder.set_filter(filter);
+        if !palette.is_empty() {
+            encoder.set_palette(palette.to_vec());
+        }
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    /// Filler pixel data with the padding bits at the end of each row cleared, since they
+    /// are not pixels and do not survive interlacing.
+    fn pixels(header: &Ihdr, seed: u32) -> Vec<u8> {
+        let mut data = noise(image_size(header).unwrap(), seed);
+        let stride = header.row_bytes(header.width);
+        let used_bits = header.width as usize * header.bits_per_pixel() % 8;
+        if used_bits != 0 {
+            for row in data.chunks_exact_mut(stride) {
+                row[stride - 1] &= 0xff << (8 - used_bits);
+            }
+        }
+        data
+    }
+
+    /// Builds an Adam7 file by hand, with unfiltered scanlines, from non-interlaced pixel data.
+    fn interlace(header: &Ihdr, data: &[u8]) -> Png {
+        let bits_per_pixel = header.bits_per_pixel();
+        let stride = header.row_bytes(header.width);
+        let mut raw = Vec::new();
+        for (pass, width, height) in adam7_passes(header) {
+            let (x_start, y_start, x_step, y_step) = pass;
+            for row in 0..height {
+                let y = y_start + row * y_step;
+                let mut pass_row = vec![0u8; header.row_bytes(width as u32)];
+                for column in 0..width {
+                    let x = x_start + column * x_step;
+                    copy_pixel(
+                        &data[y * stride..],
+                        x,
+                        &mut pass_row,
+                        column,
+                        bits_per_pixel,
+                    );
+                }
+                raw.push(0);
+                raw.extend(pass_row);
+            }
+        }
+        let mut interlaced = *header;
+        interlaced.interlace_method = 1;
+        Png::from_chunks(vec![
+            interlaced.to_chunk().unwrap(),
+            Chunk::new(ChunkType::IDAT, compression::deflate(&raw).unwrap()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ])
+    }
+
+    const COLOR_TYPES: [ColorType; 5] = [
+        ColorType::Grayscale,
+        ColorType::Rgb,
+        ColorType::Indexed,
+        ColorType::GrayscaleAlpha,
+        ColorType::Rgba,
+    ];
+
+    #[test]
+    fn test_decode_matches_reference() {
+        let bytes = include_bytes!("../ferris.png");
+        let image = Png::try_from(&bytes[..]).unwrap().decode_image().unwrap();
+        assert_eq!(image.data(), reference_decode(bytes));
+        assert_eq!((image.width(), image.height()), (50, 50));
+    }
+
+    #[test]
+    fn test_all_color_types_and_bit_depths() {
+        let filters = [
+            reference_png::FilterType::NoFilter,
+            reference_png::FilterType::Sub,
+            reference_png::FilterType::Up,
+            reference_png::FilterType::Avg,
+            reference_png::FilterType::Paeth,
+        ];
+        for color_type in COLOR_TYPES {
+            for &bit_depth in color_type.allowed_bit_depths() {
+                let header = Ihdr::new(13, 7, bit_depth, color_type);
+                let palette = if color_type == ColorType::Indexed {
+                    noise(3 << bit_depth, 7)
+                } else {
+                    Vec::new()
+                };
+                let data = noise(image_size(&header).unwrap(), bit_depth as u32 + 1);
+                for filter in filters {
+                    let bytes = reference_encode(&header, &palette, &data, filter);
+                    let image = Png::try_from(&bytes[..]).unwrap().decode_image().unwrap();
+                    assert_eq!(image.data(), reference_decode(&bytes));
+                    assert_eq!(image.data(), data, "{} {}-bit", color_type, bit_depth);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_adam7() {
+        let sizes = [(16, 11), (3, 3), (1, 1), (9, 17)];
+        for color_type in COLOR_TYPES {
+            for &bit_depth in color_type.allowed_bit_depths() {
+                for (width, height) in sizes {
+                    let header = Ihdr::new(width, height, bit_depth, color_type);
+                    let data = pixels(&header, width * height);
+                    let mut png = interlace(&header, &data);
+                    if color_type == ColorType::Indexed {
+                        let palette = Chunk::new(ChunkType::PLTE, noise(3 << bit_depth, 3));
+                        png.insert_chunk(palette, &crate::png::ChunkPosition::Index(1))
+                            .unwrap();
+                    }
+                    let image = png.decode_image().unwrap();
+                    assert_eq!(
+                        image.data(),
+                        data,
+                        "{}x{} {} {}-bit",
+                        width,
+                        height,
+                        color_type,
+                        bit_depth
+                    );
+                    assert_eq!(image.data(), reference_decode(&png.as_bytes()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let mut strategies = vec![FilterStrategy::Adaptive];
+        for filter_type in [FilterType::None, FilterType::Sub, FilterType::Paeth] {
+            strategies.push(FilterStrategy::Fixed(filter_type));
+        }
+        for color_type in COLOR_TYPES {
+            for &bit_depth in color_type.allowed_bit_depths() {
+                for interlace_method in [0, 1] {
+                    let mut header = Ihdr::new(11, 9, bit_depth, color_type);
+                    header.interlace_method = interlace_method;
+                    let palette = (color_type == ColorType::Indexed)
+                        .then(|| vec![[1, 2, 3]; 1 << bit_depth.min(8)]);
+                    let data = pixels(&header, bit_depth as u32 * 31 + 5);
+                    let image = Image::new(header, palette, data.clone()).unwrap();
+                    for filter in &strategies {
+                        let options = EncoderOptions {
+                            filter: *filter,
+                            ..EncoderOptions::default()
+                        };
+                        let png = Png::from_image(&image, &options).unwrap();
+                        assert!(png.validate().is_empty());
+                        assert_eq!(png.decode_image().unwrap(), image);
+                        assert_eq!(reference_decode(&png.as_bytes()), data);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_splits_idat() {
+        let header = Ihdr::new(64, 64, 8, ColorType::Rgb);
+        let image = Image::new(header, None, noise(64 * 64 * 3, 1)).unwrap();
+        let options = EncoderOptions {
+            max_idat_size: 1000,
+            ..EncoderOptions::default()
+        };
+        let png = Png::from_image(&image, &options).unwrap();
+        let sizes: Vec<u32> = png
+            .chunks_by_type(&ChunkType::IDAT)
+            .map(|chunk| chunk.length())
+            .collect();
+        assert!(sizes.len() > 10);
+        assert!(sizes.iter().all(|&size| size <= 1000));
+        assert!(sizes[..sizes.len() - 1].iter().all(|&size| size == 1000));
+        assert_eq!(reference_decode(&png.as_bytes()), image.data());
+
+        let gray = Image::new(
+            Ihdr::new(1, 1, 8, ColorType::Grayscale),
+            Some(vec![[0; 3]]),
+            vec![0],
+        );
+        assert!(Png::from_image(&gray.unwrap(), &EncoderOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_pixel_access() {
+        // 2x2 2-bit grayscale: 0 1 / 2 3, each row padded to a byte.
+        let header = Ihdr::new(2, 2, 2, ColorType::Grayscale);
+        let image = Image::new(header, None, vec![0b0001_0000, 0b1011_0000]).unwrap();
+        assert_eq!(image.pixel(1, 0), Pixel::Gray(1));
+        assert_eq!(image.pixel(0, 1), Pixel::Gray(2));
+        assert_eq!(image.pixel(1, 1), Pixel::Gray(3));
+
+        let header = Ihdr::new(1, 1, 16, ColorType::Rgba);
+        let image = Image::new(header, None, vec![0, 1, 2, 3, 4, 5, 0xff, 0xff]).unwrap();
+        assert_eq!(image.pixel(0, 0), Pixel::Rgba([1, 0x0203, 0x0405, 0xffff]));
+
+        assert!(Image::new(header, None, vec![0; 7]).is_err());
+        let indexed = Ihdr::new(1, 1, 8, ColorType::Indexed);
+        assert!(Image::new(indexed, None, vec![0]).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_image_data() {
+        let header = Ihdr::new(4, 4, 8, ColorType::Grayscale);
+        let short = compression::deflate(&[0; 10]).unwrap();
+        assert!(matches!(
+            decode(header, None, &short),
+            Err(Error::InvalidImageData(_))
+        ));
+        let long = compression::deflate(&[0; 100]).unwrap();
+        assert!(matches!(
+            decode(header, None, &long),
+            Err(Error::InvalidImageData(_))
+        ));
+        assert!(matches!(
+            decode(header, None, b"not zlib"),
+            Err(Error::InvalidImageData(_))
+        ));
+
+        let mut huge = Ihdr::new(30_000, 30_000, 16, ColorType::Rgba);
+        huge.interlace_method = 1;
+        assert!(matches!(
+            decode(huge, None, &short),
+            Err(Error::InvalidImageData(_))
+        ));
+    }
+}
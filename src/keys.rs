@@ -0,0 +1,249 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use chacha20poly1305::aead::OsRng;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{Error, Result};
+
+const IDENTITY_PREFIX: &str = "PNGME-SECRET-KEY-";
+const RECIPIENT_PREFIX: &str = "pngme-pub-";
+const SIGNING_KEY_PREFIX: &str = "PNGME-SIGNING-KEY-";
+const VERIFYING_KEY_PREFIX: &str = "pngme-verify-";
+
+/// An X25519 secret key used to decrypt messages sent to its `Recipient`.
+pub struct Identity {
+    secret: StaticSecret,
+}
+
+/// An X25519 public key messages can be encrypted to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    public: PublicKey,
+}
+
+/// An Ed25519 secret key used to sign embedded messages.
+pub struct SigningKey {
+    key: ed25519_dalek::SigningKey,
+}
+
+/// An Ed25519 public key used to verify signatures made by its `SigningKey`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyingKey {
+    key: ed25519_dalek::VerifyingKey,
+}
+
+impl Identity {
+    pub fn generate() -> Identity {
+        Identity {
+            secret: StaticSecret::random_from_rng(OsRng),
+        }
+    }
+
+    pub fn recipient(&self) -> Recipient {
+        Recipient {
+            public: PublicKey::from(&self.secret),
+        }
+    }
+
+    pub fn read_from(path: &Path) -> Result<Identity> {
+        Identity::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub(crate) fn secret(&self) -> &StaticSecret {
+        &self.secret
+    }
+}
+
+impl Recipient {
+    pub fn read_from(path: &Path) -> Result<Recipient> {
+        Recipient::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub(crate) fn public(&self) -> &PublicKey {
+        &self.public
+    }
+}
+
+impl SigningKey {
+    pub fn generate() -> SigningKey {
+        SigningKey {
+            key: ed25519_dalek::SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey {
+            key: self.key.verifying_key(),
+        }
+    }
+
+    pub fn read_from(path: &Path) -> Result<SigningKey> {
+        SigningKey::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub(crate) fn key(&self) -> &ed25519_dalek::SigningKey {
+        &self.key
+    }
+}
+
+impl VerifyingKey {
+    pub fn read_from(path: &Path) -> Result<VerifyingKey> {
+        VerifyingKey::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8; 32]) -> Result<VerifyingKey> {
+        let key = ed25519_dalek::VerifyingKey::from_bytes(bytes)
+            .map_err(|_| Error::InvalidKey("chave publica Ed25519 invalida"))?;
+        Ok(VerifyingKey { key })
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        self.key.as_bytes()
+    }
+
+    /// First 16 bytes of the SHA-256 of the key, in hex.
+    pub fn fingerprint(&self) -> String {
+        let digest = Sha256::digest(self.key.as_bytes());
+        to_hex(&digest[..16])
+    }
+
+    pub(crate) fn key(&self) -> &ed25519_dalek::VerifyingKey {
+        &self.key
+    }
+}
+
+impl FromStr for Identity {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = parse_key(s, IDENTITY_PREFIX)?;
+        Ok(Identity {
+            secret: StaticSecret::from(bytes),
+        })
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = parse_key(s, RECIPIENT_PREFIX)?;
+        Ok(Recipient {
+            public: PublicKey::from(bytes),
+        })
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = parse_key(s, SIGNING_KEY_PREFIX)?;
+        Ok(SigningKey {
+            key: ed25519_dalek::SigningKey::from_bytes(&bytes),
+        })
+    }
+}
+
+impl FromStr for VerifyingKey {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        VerifyingKey::from_bytes(&parse_key(s, VERIFYING_KEY_PREFIX)?)
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", IDENTITY_PREFIX, to_hex(self.secret.as_bytes()))
+    }
+}
+
+impl Display for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", RECIPIENT_PREFIX, to_hex(self.public.as_bytes()))
+    }
+}
+
+impl Display for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", SIGNING_KEY_PREFIX, to_hex(self.key.as_bytes()))
+    }
+}
+
+impl Display for VerifyingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", VERIFYING_KEY_PREFIX, to_hex(self.key.as_bytes()))
+    }
+}
+
+/// Reads the first line of a key file that is not blank or a `#` comment.
+pub(crate) fn parse_key(s: &str, prefix: &str) -> Result<[u8; 32]> {
+    let line = s
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or(Error::InvalidKey("arquivo de chave vazio"))?;
+    let hex = line
+        .strip_prefix(prefix)
+        .ok_or(Error::InvalidKey("prefixo de chave incorreto"))?;
+    from_hex(hex)?
+        .try_into()
+        .map_err(|_| Error::InvalidKey("chave com tamanho incorreto"))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn from_hex(s: &str) -> Result<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return Err(Error::InvalidKey("hexadecimal invalido"));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&s[i..i + 2], 16)
+                .map_err(|_| Error::InvalidKey("hexadecimal invalido"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_round_trip() {
+        let identity = Identity::generate();
+        let text = format!("# comment\n\n{}\n", identity);
+        let parsed = Identity::from_str(&text).unwrap();
+        assert_eq!(parsed.recipient(), identity.recipient());
+    }
+
+    #[test]
+    fn test_recipient_round_trip() {
+        let recipient = Identity::generate().recipient();
+        let parsed = Recipient::from_str(&recipient.to_string()).unwrap();
+        assert_eq!(parsed, recipient);
+    }
+
+    #[test]
+    fn test_signing_key_round_trip() {
+        let signing_key = SigningKey::generate();
+        let parsed = SigningKey::from_str(&signing_key.to_string()).unwrap();
+        assert_eq!(parsed.verifying_key(), signing_key.verifying_key());
+
+        let verifying_key = signing_key.verifying_key();
+        let parsed = VerifyingKey::from_str(&verifying_key.to_string()).unwrap();
+        assert_eq!(parsed.fingerprint(), verifying_key.fingerprint());
+        assert_eq!(parsed.fingerprint().len(), 32);
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        let recipient = Identity::generate().recipient().to_string();
+        assert!(Identity::from_str(&recipient).is_err());
+        assert!(Recipient::from_str("pngme-pub-abc").is_err());
+        assert!(Recipient::from_str("").is_err());
+    }
+}
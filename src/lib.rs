@@ -1,5 +1,27 @@
 pub mod args;
+pub mod attachment;
 pub mod chunk;
+pub mod chunk_ref;
 pub mod chunk_type;
 pub mod commands;
+pub mod compression;
+pub mod crypto;
+pub mod envelope;
+pub mod error;
+pub mod filter;
+pub mod hexdump;
+pub mod ihdr;
+pub mod image;
+pub mod keys;
+pub mod lsb;
 pub mod png;
+pub mod png_ref;
+pub mod reader;
+pub mod signature;
+pub mod split;
+pub mod text;
+pub mod validate;
+pub mod writer;
+
+pub use error::{Error, ErrorCategory};
+pub type Result<T> = std::result::Result<T, Error>;
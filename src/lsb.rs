@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+use crate::ihdr::{ColorType, Ihdr};
+use crate::image::Image;
+use crate::{Error, Result};
+
+/// Marks a payload hidden in the pixels.
+const MAGIC: [u8; 3] = *b"PMl";
+const VERSION: u8 = 1;
+/// `magic | version | payload length (u32)`
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+
+/// Which bits of which samples carry the payload.
+#[derive(Debug, Clone, Copy)]
+pub struct LsbOptions<'a> {
+    /// Low bits used in every selected sample, from 1 to 8.
+    pub bits_per_channel: u8,
+    /// Also use the alpha channel; off by default, as changes there show on some backgrounds.
+    pub include_alpha: bool,
+    /// Visit the pixels in an order derived from this key instead of row by row.
+    pub key: Option<&'a [u8]>,
+}
+
+impl Default for LsbOptions<'_> {
+    fn default() -> Self {
+        LsbOptions {
+            bits_per_channel: 1,
+            include_alpha: false,
+            key: None,
+        }
+    }
+}
+
+/// Payload bytes that fit in an image with this header.
+pub fn capacity(header: &Ihdr, options: &LsbOptions) -> Result<usize> {
+    let channels = selected_channels(header, options)?;
+    let samples = header.width as usize * header.height as usize * channels;
+    Ok((samples * options.bits_per_channel as usize / 8).saturating_sub(HEADER_LEN))
+}
+
+/// Hides `payload` in the low bits of the pixels of `image`.
+pub fn embed(image: &mut Image, payload: &[u8], options: &LsbOptions) -> Result<()> {
+    let capacity = capacity(image.header(), options)?;
+    if payload.len() > capacity {
+        return Err(Error::CapacityExceeded {
+            needed: payload.len(),
+            capacity,
+        });
+    }
+
+    let mut framed = Vec::with_capacity(HEADER_LEN + payload.len());
+    framed.extend_from_slice(&MAGIC);
+    framed.push(VERSION);
+    framed.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    framed.extend_from_slice(payload);
+
+    let bits = options.bits_per_channel as usize;
+    let mask = low_bits_mask(bits);
+    let slots = sample_slots(image.header(), options)?;
+    let data = image.data_mut();
+    let mut reader = BitReader::new(&framed);
+    for slot in slots {
+        if reader.is_done() {
+            break;
+        }
+        data[slot] = (data[slot] & !mask) | reader.read(bits);
+    }
+    Ok(())
+}
+
+/// Recovers a payload hidden by `embed` with the same options.
+pub fn extract(image: &Image, options: &LsbOptions) -> Result<Vec<u8>> {
+    let bits = options.bits_per_channel as usize;
+    let mask = low_bits_mask(bits);
+    let data = image.data();
+    let mut writer = BitWriter::default();
+    let mut expected = HEADER_LEN;
+    for slot in sample_slots(image.header(), options)? {
+        writer.write(data[slot] & mask, bits);
+        if writer.bytes.len() < expected {
+            continue;
+        }
+        if expected == HEADER_LEN {
+            let header = &writer.bytes[..HEADER_LEN];
+            if header[..MAGIC.len()] != MAGIC {
+                return Err(Error::InvalidPayload(
+                    "nenhuma mensagem nos pixels (chave ou opcoes incorretas?)",
+                ));
+            }
+            if header[MAGIC.len()] != VERSION {
+                return Err(Error::UnsupportedVersion(header[MAGIC.len()]));
+            }
+            let length = u32::from_be_bytes(header[MAGIC.len() + 1..].try_into().unwrap());
+            if length as usize > capacity(image.header(), options)? {
+                return Err(Error::InvalidPayload("mensagem nos pixels incompleta"));
+            }
+            expected = HEADER_LEN + length as usize;
+        }
+        if writer.bytes.len() >= expected {
+            writer.bytes.truncate(expected);
+            return Ok(writer.bytes.split_off(HEADER_LEN));
+        }
+    }
+    Err(Error::InvalidPayload("mensagem nos pixels incompleta"))
+}
+
+/// Number of samples per pixel that carry payload bits.
+fn selected_channels(header: &Ihdr, options: &LsbOptions) -> Result<usize> {
+    if !(1..=8).contains(&options.bits_per_channel) {
+        return Err(Error::UnsupportedCarrier("use de 1 a 8 bits por canal"));
+    }
+    if header.bit_depth < 8 {
+        return Err(Error::UnsupportedCarrier(
+            "a profundidade de bits deve ser 8 ou 16",
+        ));
+    }
+    match header.color_type {
+        ColorType::Indexed => Err(Error::UnsupportedCarrier(
+            "imagens com paleta nao sao suportadas",
+        )),
+        ColorType::Grayscale | ColorType::Rgb => Ok(header.color_type.channels() as usize),
+        ColorType::GrayscaleAlpha | ColorType::Rgba => {
+            let channels = header.color_type.channels() as usize;
+            Ok(if options.include_alpha {
+                channels
+            } else {
+                channels - 1
+            })
+        }
+    }
+}
+
+/// Byte offsets, in the order they are used, of the least significant byte of every
+/// selected sample. They are produced as needed, so hiding a short payload only walks
+/// the first few pixels of the order.
+fn sample_slots(header: &Ihdr, options: &LsbOptions) -> Result<impl Iterator<Item = usize>> {
+    let selected = selected_channels(header, options)?;
+    let channels = header.color_type.channels() as usize;
+    let bytes_per_sample = header.bit_depth as usize / 8;
+    let stride = header.row_bytes(header.width);
+    let width = header.width as usize;
+    let pixels = PixelOrder::new(width * header.height as usize, options.key);
+
+    Ok(pixels.flat_map(move |pixel| {
+        let start = (pixel / width) * stride + (pixel % width) * channels * bytes_per_sample;
+        (0..selected).map(move |channel| start + (channel + 1) * bytes_per_sample - 1)
+    }))
+}
+
+fn low_bits_mask(bits: usize) -> u8 {
+    (0xffu16 >> (8 - bits)) as u8
+}
+
+/// Pixel indices row by row, or shuffled by the key.
+///
+/// The shuffle is a Fisher-Yates run one step per pixel taken, keeping only the
+/// entries it has moved, so memory grows with the pixels used rather than the image.
+struct PixelOrder {
+    count: usize,
+    next: usize,
+    stream: Option<KeyStream>,
+    moved: HashMap<usize, usize>,
+}
+
+impl PixelOrder {
+    fn new(count: usize, key: Option<&[u8]>) -> PixelOrder {
+        PixelOrder {
+            count,
+            next: 0,
+            stream: key.map(KeyStream::new),
+            moved: HashMap::new(),
+        }
+    }
+}
+
+impl Iterator for PixelOrder {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let i = self.next;
+        if i >= self.count {
+            return None;
+        }
+        self.next += 1;
+        let Some(stream) = &mut self.stream else {
+            return Some(i);
+        };
+        let j = i + (stream.next_u64() % (self.count - i) as u64) as usize;
+        let at_j = self.moved.remove(&j).unwrap_or(j);
+        if j != i {
+            let at_i = self.moved.remove(&i).unwrap_or(i);
+            self.moved.insert(j, at_i);
+        }
+        Some(at_j)
+    }
+}
+
+/// Pseudo-random numbers from SHA-256 in counter mode, the same for the same key.
+struct KeyStream {
+    seed: [u8; 32],
+    counter: u64,
+    block: [u8; 32],
+    used: usize,
+}
+
+impl KeyStream {
+    fn new(key: &[u8]) -> KeyStream {
+        let mut hasher = Sha256::new();
+        hasher.update(b"pngme lsb order");
+        hasher.update(key);
+        KeyStream {
+            seed: hasher.finalize().into(),
+            counter: 0,
+            block: [0; 32],
+            used: 32,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.used == self.block.len() {
+            let mut hasher = Sha256::new();
+            hasher.update(self.seed);
+            hasher.update(self.counter.to_be_bytes());
+            self.block = hasher.finalize().into();
+            self.counter += 1;
+            self.used = 0;
+        }
+        let value = u64::from_be_bytes(self.block[self.used..self.used + 8].try_into().unwrap());
+        self.used += 8;
+        value
+    }
+}
+
+/// Reads bits most significant first, padding with zeros past the end.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader { bytes, position: 0 }
+    }
+
+    fn is_done(&self) -> bool {
+        self.position >= self.bytes.len() * 8
+    }
+
+    fn read(&mut self, count: usize) -> u8 {
+        let mut value = 0;
+        for _ in 0..count {
+            let bit = self
+                .bytes
+                .get(self.position / 8)
+                .map_or(0, |byte| (byte >> (7 - self.position % 8)) & 1);
+            value = (value << 1) | bit;
+            self.position += 1;
+        }
+        value
+    }
+}
+
+/// Collects bits most significant first into whole bytes.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u16,
+    pending: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u8, count: usize) {
+        for i in (0..count).rev() {
+            self.current = (self.current << 1) | ((value >> i) & 1) as u16;
+            self.pending += 1;
+            if self.pending == 8 {
+                self.bytes.push(self.current as u8);
+                self.current = 0;
+                self.pending = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn carrier(color_type: ColorType, bit_depth: u8) -> Image {
+        let header = Ihdr::new(40, 20, bit_depth, color_type);
+        let size = header.row_bytes(40) * 20;
+        let data = (0..size).map(|i| (i * 7) as u8).collect();
+        Image::new(header, None, data).unwrap()
+    }
+
+    #[test]
+    fn test_lsb_round_trip() {
+        let payload = b"hidden in plain sight";
+        for (color_type, bit_depth) in [
+            (ColorType::Rgb, 8),
+            (ColorType::Rgba, 8),
+            (ColorType::Grayscale, 16),
+            (ColorType::GrayscaleAlpha, 8),
+        ] {
+            for bits_per_channel in [1, 2, 4, 8] {
+                let options = LsbOptions {
+                    bits_per_channel,
+                    key: Some(b"key"),
+                    ..LsbOptions::default()
+                };
+                let original = carrier(color_type, bit_depth);
+                let mut image = original.clone();
+                embed(&mut image, payload, &options).unwrap();
+                assert_eq!(extract(&image, &options).unwrap(), payload);
+
+                // Only the selected low bits change.
+                let mask = low_bits_mask(bits_per_channel as usize);
+                for (before, after) in original.data().iter().zip(image.data()) {
+                    assert_eq!(before & !mask, after & !mask);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lsb_key_and_alpha() {
+        let original = carrier(ColorType::Rgba, 8);
+        let options = LsbOptions {
+            key: Some(b"right key"),
+            ..LsbOptions::default()
+        };
+        let mut image = original.clone();
+        embed(&mut image, b"secret", &options).unwrap();
+
+        let wrong_key = LsbOptions {
+            key: Some(b"wrong key"),
+            ..options
+        };
+        assert!(extract(&image, &wrong_key).is_err());
+        assert!(extract(&image, &LsbOptions::default()).is_err());
+
+        // Alpha is left alone unless asked for.
+        for (before, after) in original.data().iter().zip(image.data()).skip(3).step_by(4) {
+            assert_eq!(before, after);
+        }
+    }
+
+    #[test]
+    fn test_pixel_order() {
+        let plain: Vec<usize> = PixelOrder::new(100, None).collect();
+        assert_eq!(plain, (0..100).collect::<Vec<_>>());
+
+        let shuffled: Vec<usize> = PixelOrder::new(100, Some(b"key")).collect();
+        assert_ne!(shuffled, plain);
+        assert_eq!(
+            shuffled,
+            PixelOrder::new(100, Some(b"key")).collect::<Vec<_>>()
+        );
+        let mut sorted = shuffled.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, plain);
+    }
+
+    #[test]
+    fn test_lsb_capacity() {
+        let header = Ihdr::new(20, 10, 8, ColorType::Rgba);
+        assert_eq!(
+            capacity(&header, &LsbOptions::default()).unwrap(),
+            20 * 10 * 3 / 8 - HEADER_LEN
+        );
+        let all = LsbOptions {
+            bits_per_channel: 2,
+            include_alpha: true,
+            key: None,
+        };
+        assert_eq!(
+            capacity(&header, &all).unwrap(),
+            20 * 10 * 4 * 2 / 8 - HEADER_LEN
+        );
+
+        let mut image = carrier(ColorType::Rgba, 8);
+        let too_big = vec![0u8; 300];
+        assert!(matches!(
+            embed(&mut image, &too_big, &LsbOptions::default()),
+            Err(Error::CapacityExceeded {
+                needed: 300,
+                capacity: 292
+            })
+        ));
+
+        let indexed = Ihdr::new(4, 4, 8, ColorType::Indexed);
+        assert!(capacity(&indexed, &LsbOptions::default()).is_err());
+        let low_depth = Ihdr::new(4, 4, 4, ColorType::Grayscale);
+        assert!(capacity(&low_depth, &LsbOptions::default()).is_err());
+    }
+}
@@ -1,39 +1,40 @@
 use std::fmt::Display;
+use std::io::{Read, Write};
+
+use sha2::{Digest, Sha256};
 
 use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::image::{self, EncoderOptions, Image};
+use crate::reader::PngReader;
+use crate::text::{self, InternationalTextChunk, TextChunk, TextEntry};
+use crate::validate::{self, Diagnostic};
+use crate::writer::PngWriter;
+use crate::Error;
 
 pub struct Png {
     chunks: Vec<Chunk>,
 }
 
+/// Where `Png::insert_chunk` puts a new chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ChunkPosition {
+    /// Right before IEND, or at the end if there is none.
+    #[default]
+    BeforeEnd,
+    /// Before the first chunk of this type.
+    Before(ChunkType),
+    /// After the last chunk of this type.
+    After(ChunkType),
+    /// At this index in `chunks()`.
+    Index(usize),
+}
+
 impl TryFrom<&[u8]> for Png {
-    type Error = &'static str;
+    type Error = Error;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if value.len() < 8 {
-            return Err("Arquivo muito pequeno");
-        }
-
-        let mut header = [0u8; 8];
-        header.clone_from_slice(&value[..8]);
-        if header != Self::STANDARD_HEADER {
-            return Err("Cabecalho incorreto");
-        }
-
-        let mut chunks = Vec::new();
-        let mut current_position = 8;
-        loop {
-            let length_bytes = &value[current_position..current_position + 4];
-            let length = u32::from_be_bytes(length_bytes.try_into().unwrap());
-            let chunk_size = 12 + length;
-            let end_chunk = current_position + chunk_size as usize;
-            chunks.push(Chunk::try_from(value[current_position..].as_ref()).unwrap());
-            if end_chunk >= value.len() {
-                break;
-            }
-            current_position = end_chunk;
-        }
-
-        Ok(Png { chunks })
+        Png::from_reader(value)
     }
 }
 
@@ -46,52 +47,258 @@ impl Display for Png {
 }
 
 impl Png {
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png { chunks }
     }
 
+    pub fn from_reader<R: Read>(reader: R) -> crate::Result<Png> {
+        let mut chunks = Vec::new();
+        for item in PngReader::new(reader)? {
+            chunks.extend(item?.into_chunk());
+        }
+        Ok(Png { chunks })
+    }
+
+    pub fn write_to<W: Write>(&self, writer: W) -> crate::Result<W> {
+        let mut png_writer = PngWriter::new(writer)?;
+        for chunk in &self.chunks {
+            png_writer.write_chunk(chunk)?;
+        }
+        png_writer.finish()
+    }
+
     pub fn chunks(&self) -> &Vec<Chunk> {
         &self.chunks
     }
 
-    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+    /// The first chunk of `chunk_type`.
+    pub fn chunk_by_type(&self, chunk_type: &ChunkType) -> Option<&Chunk> {
+        self.chunks_by_type(chunk_type).next()
+    }
+
+    /// Every chunk of `chunk_type`, in file order.
+    pub fn chunks_by_type(&self, chunk_type: &ChunkType) -> impl Iterator<Item = &Chunk> + '_ {
+        let chunk_type = *chunk_type;
         self.chunks
             .iter()
-            .filter(|&chunk| chunk.chunk_type().to_string() == chunk_type)
-            .next()
+            .filter(move |chunk| chunk.chunk_type() == &chunk_type)
     }
 
+    /// Inserts `chunk` right before IEND, so it is not left as trailing data.
+    /// Does not check ordering rules; see `insert_chunk`.
     pub fn append_chunk(&mut self, chunk: Chunk) {
-        self.chunks.push(chunk);
+        let index = self.end_index();
+        self.chunks.insert(index, chunk);
+    }
+
+    /// Inserts `chunk` at `position` and returns its index. Fails if the type to insert next to
+    /// is missing, or if the chunk would break an ordering rule that held before, such as going
+    /// before IHDR, after IEND, between two IDATs, or on the wrong side of PLTE.
+    ///
+    /// A safe-to-copy ancillary chunk cannot be placed next to a critical chunk other than
+    /// IHDR, IDAT or IEND: editors copy it without knowing what it means and only keep it on
+    /// the same side of IDAT, so it may end up anywhere relative to PLTE.
+    pub fn insert_chunk(&mut self, chunk: Chunk, position: &ChunkPosition) -> crate::Result<usize> {
+        if let ChunkPosition::Before(anchor) | ChunkPosition::After(anchor) = position {
+            let new_type = chunk.chunk_type();
+            let movable = !new_type.is_critical() && new_type.is_safe_to_copy();
+            let fixed = [ChunkType::IHDR, ChunkType::IDAT, ChunkType::IEND];
+            if movable && anchor.is_critical() && !fixed.contains(anchor) {
+                return Err(Error::InvalidPosition(format!(
+                    "{} e seguro para copia e editores nao mantem sua posicao em relacao a {}",
+                    new_type, anchor
+                )));
+            }
+        }
+
+        let index = match position {
+            ChunkPosition::BeforeEnd => self.end_index(),
+            ChunkPosition::Before(chunk_type) => self
+                .chunks
+                .iter()
+                .position(|chunk| chunk.chunk_type() == chunk_type)
+                .ok_or_else(|| Error::ChunkNotFound(chunk_type.to_string()))?,
+            ChunkPosition::After(chunk_type) => self
+                .chunks
+                .iter()
+                .rposition(|chunk| chunk.chunk_type() == chunk_type)
+                .map(|index| index + 1)
+                .ok_or_else(|| Error::ChunkNotFound(chunk_type.to_string()))?,
+            ChunkPosition::Index(index) if *index <= self.chunks.len() => *index,
+            ChunkPosition::Index(index) => {
+                return Err(Error::InvalidPosition(format!(
+                    "indice {} alem do fim do arquivo ({} chunks)",
+                    index,
+                    self.chunks.len()
+                )))
+            }
+        };
+
+        let broken_before = self.ordering_problems().len();
+        self.chunks.insert(index, chunk);
+        let problems = self.ordering_problems();
+        if problems.len() > broken_before {
+            self.chunks.remove(index);
+            let problem = problems
+                .iter()
+                .find(|problem| problem.index() == Some(index))
+                .unwrap_or(&problems[0]);
+            return Err(Error::InvalidPosition(problem.to_string()));
+        }
+        Ok(index)
+    }
+
+    /// Index of IEND, or the number of chunks if there is none.
+    fn end_index(&self) -> usize {
+        self.chunks
+            .iter()
+            .rposition(|chunk| *chunk.chunk_type() == ChunkType::IEND)
+            .unwrap_or(self.chunks.len())
+    }
+
+    fn ordering_problems(&self) -> Vec<Diagnostic> {
+        self.validate()
+            .into_iter()
+            .filter(Diagnostic::is_ordering_problem)
+            .collect()
     }
 
-    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, &'static str> {
+    /// Removes the first chunk of `chunk_type`.
+    pub fn remove_chunk(&mut self, chunk_type: &ChunkType) -> crate::Result<Chunk> {
         if let Some(pos) = self
             .chunks
             .iter()
-            .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+            .position(|chunk| chunk.chunk_type() == chunk_type)
         {
             let chunk = self.chunks.remove(pos);
             return Ok(chunk);
         }
-        Err("Chunk nao encontrado")
+        Err(Error::ChunkNotFound(chunk_type.to_string()))
     }
 
-    pub fn as_bytes(&self) -> Vec<u8> {
-        let chunk_bytes: Vec<u8> = self
+    /// Removes every chunk of `chunk_type`, returning them in file order.
+    pub fn remove_chunks_by_type(&mut self, chunk_type: &ChunkType) -> Vec<Chunk> {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| chunk.chunk_type() == chunk_type);
+        self.chunks = kept;
+        removed
+    }
+
+    pub fn remove_chunk_at(&mut self, index: usize) -> crate::Result<Chunk> {
+        if index >= self.chunks.len() {
+            return Err(Error::ChunkNotFound(format!("#{}", index)));
+        }
+        Ok(self.chunks.remove(index))
+    }
+
+    /// The parsed IHDR chunk, which the spec requires to come first.
+    pub fn header(&self) -> crate::Result<Ihdr> {
+        match self.chunks.first() {
+            Some(chunk) if *chunk.chunk_type() == ChunkType::IHDR => Ihdr::try_from(chunk),
+            _ => Err(Error::ChunkNotFound(ChunkType::IHDR.to_string())),
+        }
+    }
+
+    /// A new file holding `image`, see `image::encode`.
+    pub fn from_image(image: &Image, options: &EncoderOptions) -> crate::Result<Png> {
+        image::encode(image, options)
+    }
+
+    /// Swaps the IDAT chunks for a fresh encoding of `image`, keeping every other chunk.
+    /// The image must have the same header as the file.
+    pub fn replace_image_data(
+        &mut self,
+        image: &Image,
+        options: &EncoderOptions,
+    ) -> crate::Result<()> {
+        if self.header()? != *image.header() {
+            return Err(Error::InvalidImageData(
+                "a imagem nao corresponde ao cabecalho do arquivo",
+            ));
+        }
+        let first = self
             .chunks
             .iter()
-            .flat_map(|chunk| chunk.as_bytes())
-            .collect();
+            .position(|chunk| *chunk.chunk_type() == ChunkType::IDAT)
+            .ok_or_else(|| Error::ChunkNotFound(ChunkType::IDAT.to_string()))?;
+        let encoded = image::encode(image, options)?;
+        self.remove_chunks_by_type(&ChunkType::IDAT);
+        let idat = encoded
+            .chunks
+            .into_iter()
+            .filter(|chunk| *chunk.chunk_type() == ChunkType::IDAT);
+        self.chunks.splice(first..first, idat);
+        Ok(())
+    }
 
-        Self::STANDARD_HEADER
+    /// Inflates and unfilters the IDAT data into pixels.
+    pub fn decode_image(&self) -> crate::Result<Image> {
+        Image::try_from(self)
+    }
+
+    /// Checks the chunk layout against the PNG spec; an empty list means the file is valid.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        validate::validate_chunks(
+            self.chunks
+                .iter()
+                .map(|chunk| (chunk.chunk_type(), chunk.data())),
+        )
+    }
+
+    /// Every tEXt, zTXt and iTXt chunk, decoded, in file order.
+    ///
+    /// A chunk that cannot be decoded gives an error in its place, so the
+    /// other entries can still be read.
+    pub fn text_entries(&self) -> Vec<crate::Result<TextEntry>> {
+        self.chunks
             .iter()
-            .chain(chunk_bytes.iter())
-            .copied()
+            .filter(|chunk| text::is_text_chunk_type(chunk.chunk_type()))
+            .map(TextEntry::try_from)
             .collect()
     }
+
+    /// Replaces every textual chunk with keyword `key` by one holding `value`, stored as
+    /// tEXt when it fits in Latin-1 and as iTXt otherwise.
+    pub fn set_text(&mut self, key: &str, value: &str) -> crate::Result<()> {
+        let chunk = if text::is_latin1(value) {
+            TextChunk::new(key, value).to_chunk()?
+        } else {
+            InternationalTextChunk::new(key, value).to_chunk()?
+        };
+
+        let has_key = |chunk: &Chunk| text::keyword_of(chunk).as_deref() == Some(key);
+        match self.chunks.iter().position(has_key) {
+            Some(pos) => {
+                self.chunks.retain(|chunk| !has_key(chunk));
+                self.chunks.insert(pos, chunk);
+            }
+            None => self.append_chunk(chunk),
+        }
+        Ok(())
+    }
+
+    /// SHA-256 over the critical chunks, plus the ancillary chunks whose type is in `extra`,
+    /// in file order. Each chunk contributes its type, length and data.
+    pub fn integrity_digest(&self, extra: &[ChunkType]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for chunk in &self.chunks {
+            let chunk_type = chunk.chunk_type();
+            if chunk_type.is_critical() || extra.contains(chunk_type) {
+                hasher.update(chunk_type.bytes());
+                hasher.update(chunk.length().to_be_bytes());
+                hasher.update(chunk.data());
+            }
+        }
+        hasher.finalize().into()
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.write_to(Vec::new())
+            .expect("writing to a Vec cannot fail")
+    }
 }
 
 #[cfg(test)]
@@ -99,7 +306,9 @@ mod tests {
     use super::*;
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
+    use crate::ihdr::ColorType;
     use std::convert::TryFrom;
+    use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
         vec![
@@ -192,6 +401,18 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_invalid_chunk_reports_offset() {
+        let mut bytes = testing_png().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        match Png::try_from(bytes.as_ref()) {
+            Err(Error::CrcMismatch { offset, .. }) => assert_eq!(offset, 8 + 32 + 30),
+            _ => panic!("expected a CRC mismatch"),
+        }
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -202,7 +423,9 @@ mod tests {
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
-        let chunk = png.chunk_by_type("FrSt").unwrap();
+        let chunk = png
+            .chunk_by_type(&ChunkType::from_str("FrSt").unwrap())
+            .unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
@@ -211,18 +434,326 @@ mod tests {
     fn test_append_chunk() {
         let mut png = testing_png();
         png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
-        let chunk = png.chunk_by_type("TeSt").unwrap();
+        let chunk = png
+            .chunk_by_type(&ChunkType::from_str("TeSt").unwrap())
+            .unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_repeated_chunk_types() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "Second").unwrap());
+        let middle = ChunkType::from_str("miDl").unwrap();
+
+        let messages: Vec<String> = png
+            .chunks_by_type(&middle)
+            .map(|chunk| chunk.data_as_string().unwrap())
+            .collect();
+        assert_eq!(messages, ["I am another chunk", "Second"]);
+
+        let removed = png.remove_chunks_by_type(&middle);
+        assert_eq!(removed.len(), 2);
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.chunk_by_type(&middle).is_none());
+    }
+
+    #[test]
+    fn test_remove_chunk_at() {
+        let mut png = testing_png();
+        let chunk = png.remove_chunk_at(1).unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "miDl");
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.remove_chunk_at(2).is_err());
+    }
+
+    #[test]
+    fn test_append_chunk_goes_before_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .rev()
+            .take(2)
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IEND", "ruSt"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_positions() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let chunk = || chunk_from_strings("ruSt", "Message").unwrap();
+        let idat = ChunkType::from_str("IDAT").unwrap();
+        let ihdr = ChunkType::from_str("IHDR").unwrap();
+
+        let first_idat = png
+            .chunks()
+            .iter()
+            .position(|chunk| chunk.chunk_type() == &idat)
+            .unwrap();
+        assert_eq!(
+            png.insert_chunk(chunk(), &ChunkPosition::Before(idat))
+                .unwrap(),
+            first_idat
+        );
+        assert_eq!(
+            png.insert_chunk(chunk(), &ChunkPosition::After(ihdr))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            png.insert_chunk(chunk(), &ChunkPosition::Index(2)).unwrap(),
+            2
+        );
+        assert!(png
+            .insert_chunk(
+                chunk(),
+                &ChunkPosition::Before(ChunkType::from_str("zzZz").unwrap())
+            )
+            .is_err());
+        assert_eq!(png.validate().len(), 1, "only the fixture's own RuSt chunk");
+    }
+
+    #[test]
+    fn test_insert_chunk_keeps_ordering_rules() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let chunk = || chunk_from_strings("ruSt", "Message").unwrap();
+        let len = png.chunks().len();
+
+        for position in [
+            ChunkPosition::Index(0),
+            ChunkPosition::Index(len),
+            ChunkPosition::After(ChunkType::from_str("IEND").unwrap()),
+            ChunkPosition::Index(len + 1),
+        ] {
+            assert!(
+                matches!(
+                    png.insert_chunk(chunk(), &position),
+                    Err(Error::InvalidPosition(_))
+                ),
+                "{:?}",
+                position
+            );
+        }
+        let gama = chunk_from_strings("gAMA", "\0\0\0\0").unwrap();
+        assert!(png.insert_chunk(gama, &ChunkPosition::BeforeEnd).is_err());
+        assert_eq!(png.chunks().len(), len);
+    }
+
+    #[test]
+    fn test_insert_chunk_next_to_palette() {
+        let header = Ihdr::new(2, 2, 8, ColorType::Indexed);
+        let image = Image::new(header, Some(vec![[0; 3], [255; 3]]), vec![0, 1, 1, 0]).unwrap();
+        let mut png = Png::from_image(&image, &EncoderOptions::default()).unwrap();
+        let safe = || chunk_from_strings("ruSt", "Message").unwrap();
+        let position_dependent = || chunk_from_strings("ruST", "Message").unwrap();
+
+        for position in [
+            ChunkPosition::Before(ChunkType::PLTE),
+            ChunkPosition::After(ChunkType::PLTE),
+        ] {
+            assert!(matches!(
+                png.insert_chunk(safe(), &position),
+                Err(Error::InvalidPosition(_))
+            ));
+        }
+        // Unsafe-to-copy chunks are dropped rather than moved, so they may depend on PLTE.
+        assert_eq!(
+            png.insert_chunk(position_dependent(), &ChunkPosition::After(ChunkType::PLTE))
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            png.insert_chunk(safe(), &ChunkPosition::Before(ChunkType::IDAT))
+                .unwrap(),
+            3
+        );
+
+        // The same goes for critical chunks pngme does not know, like the fixture's RuSt.
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let rust = ChunkType::from_str("RuSt").unwrap();
+        assert!(png
+            .insert_chunk(safe(), &ChunkPosition::After(rust))
+            .is_err());
+        assert!(png
+            .insert_chunk(position_dependent(), &ChunkPosition::After(rust))
+            .is_ok());
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
         png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
-        png.remove_chunk("TeSt").unwrap();
-        let chunk = png.chunk_by_type("TeSt");
+        png.remove_chunk(&ChunkType::from_str("TeSt").unwrap())
+            .unwrap();
+        let chunk = png.chunk_by_type(&ChunkType::from_str("TeSt").unwrap());
         assert!(chunk.is_none());
+        assert!(matches!(
+            png.remove_chunk(&ChunkType::from_str("TeSt").unwrap()),
+            Err(Error::ChunkNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_truncated_files_are_errors() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut boundaries = vec![8];
+        for chunk in png.chunks() {
+            boundaries.push(boundaries.last().unwrap() + chunk.as_bytes().len());
+        }
+
+        for end in 0..PNG_FILE.len() {
+            let result = Png::try_from(&PNG_FILE[..end]);
+            if !boundaries.contains(&end) {
+                assert!(result.is_err(), "prefix {}", end);
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_inputs_are_errors() {
+        #[rustfmt::skip]
+        let corpus: Vec<Vec<u8>> = vec![
+            // signature followed by a partial length field
+            vec![137, 80, 78, 71, 13, 10, 26, 10, 0, 0],
+            // length larger than the remaining input
+            vec![137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 1, 0, 73, 72, 68, 82, 0, 0, 0, 0],
+            // length above the spec limit
+            vec![137, 80, 78, 71, 13, 10, 26, 10, 255, 255, 255, 255, 73, 72, 68, 82, 0, 0, 0, 0],
+            // length that would overflow 32-bit offset arithmetic
+            vec![137, 80, 78, 71, 13, 10, 26, 10, 127, 255, 255, 250, 73, 72, 68, 82, 0, 0, 0, 0],
+        ];
+
+        for input in corpus {
+            assert!(Png::try_from(input.as_ref()).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_integrity_digest_ignores_ancillary_chunks() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let digest = png.integrity_digest(&[]);
+
+        let mut with_metadata = Png::try_from(&PNG_FILE[..]).unwrap();
+        with_metadata.append_chunk(chunk_from_strings("ruSt", "metadata").unwrap());
+        assert_eq!(with_metadata.integrity_digest(&[]), digest);
+
+        let included = [ChunkType::from_str("ruSt").unwrap()];
+        assert_ne!(with_metadata.integrity_digest(&included), digest);
+
+        let mut altered = Png::try_from(&PNG_FILE[..]).unwrap();
+        altered
+            .remove_chunk(&ChunkType::from_str("IEND").unwrap())
+            .unwrap();
+        assert_ne!(altered.integrity_digest(&[]), digest);
+    }
+
+    #[test]
+    fn test_image_file_is_valid() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        // The fixture carries a message in a critical, non-standard RuSt chunk.
+        assert!(matches!(
+            png.validate()[..],
+            [Diagnostic::UnknownCriticalChunk { index: 5, .. }]
+        ));
+        png.remove_chunk(&ChunkType::from_str("RuSt").unwrap())
+            .unwrap();
+        assert_eq!(png.validate(), vec![]);
+    }
+
+    #[test]
+    fn test_header() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let header = png.header().unwrap();
+        assert_eq!((header.width, header.height), (50, 50));
+        assert_eq!(header.bit_depth, 8);
+        assert_eq!(header.color_type, crate::ihdr::ColorType::Rgba);
+
+        assert!(matches!(
+            testing_png().header(),
+            Err(Error::ChunkNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_replace_image_data() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut image = png.decode_image().unwrap();
+        image.data_mut()[0] ^= 1;
+        let types_before: Vec<_> = png.chunks().iter().map(|c| *c.chunk_type()).collect();
+
+        let options = EncoderOptions {
+            max_idat_size: 1024,
+            ..EncoderOptions::default()
+        };
+        png.replace_image_data(&image, &options).unwrap();
+        assert_eq!(png.decode_image().unwrap(), image);
+        let idat_count = png.chunks_by_type(&ChunkType::IDAT).count();
+        assert!(idat_count > 1);
+        // Everything but the IDAT chunks stays where it was.
+        let types_after: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|c| *c.chunk_type())
+            .filter(|t| *t != ChunkType::IDAT)
+            .collect();
+        let expected: Vec<_> = types_before
+            .into_iter()
+            .filter(|t| *t != ChunkType::IDAT)
+            .collect();
+        assert_eq!(types_after, expected);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "sRGB");
+
+        let other = Image::new(
+            crate::ihdr::Ihdr::new(1, 1, 8, crate::ihdr::ColorType::Rgb),
+            None,
+            vec![0; 3],
+        )
+        .unwrap();
+        assert!(png.replace_image_data(&other, &options).is_err());
+    }
+
+    #[test]
+    fn test_text_entries() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.set_text("Author", "Gustavo").unwrap();
+        png.set_text("Title", "日本").unwrap();
+        png.set_text("Author", "Félix").unwrap();
+
+        let entries: Vec<TextEntry> = png
+            .text_entries()
+            .into_iter()
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(&entries[0], TextEntry::Text(text) if text.text == "Félix"));
+        assert!(matches!(&entries[1], TextEntry::International(text) if text.text == "日本"));
+
+        let reparsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        let reparsed_entries: Vec<TextEntry> = reparsed
+            .text_entries()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(reparsed_entries, entries);
+        assert!(png.set_text("", "empty keyword").is_err());
+    }
+
+    #[test]
+    fn test_text_entries_keep_going_after_bad_chunk() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.set_text("Author", "Gustavo").unwrap();
+        png.append_chunk(Chunk::new(ChunkType::ZTXT, b"Broken\0\0not zlib".to_vec()));
+        png.set_text("Title", "ferris").unwrap();
+
+        let entries = png.text_entries();
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(&entries[0], Ok(entry) if entry.text() == "Gustavo"));
+        assert!(entries[1].is_err());
+        assert!(matches!(&entries[2], Ok(entry) if entry.keyword() == "Title"));
     }
 
     #[test]
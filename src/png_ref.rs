@@ -0,0 +1,120 @@
+use std::fmt::Display;
+
+use crate::chunk_ref::ChunkRef;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// A PNG whose chunks borrow their data from the parsed buffer, e.g. a memory-mapped file.
+pub struct PngRef<'a> {
+    bytes: &'a [u8],
+    chunks: Vec<ChunkRef<'a>>,
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = Error;
+    fn try_from(value: &'a [u8]) -> Result<Self> {
+        if value.len() < 8 {
+            return Err(Error::Truncated {
+                offset: 0,
+                needed: 8,
+            });
+        }
+        if value[..8] != Png::STANDARD_HEADER {
+            return Err(Error::BadSignature);
+        }
+
+        let mut chunks = Vec::new();
+        let mut current_position = 8;
+        while current_position < value.len() {
+            let chunk = ChunkRef::parse(value, current_position)?;
+            current_position += 12 + chunk.length() as usize;
+            chunks.push(chunk);
+        }
+
+        Ok(PngRef {
+            bytes: value,
+            chunks,
+        })
+    }
+}
+
+impl Display for PngRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let png_chars = String::from_utf8_lossy(self.bytes);
+        write!(f, "{}", png_chars)
+    }
+}
+
+impl<'a> PngRef<'a> {
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &ChunkType) -> Option<&ChunkRef<'a>> {
+        self.chunks_by_type(chunk_type).next()
+    }
+
+    pub fn chunks_by_type(
+        &self,
+        chunk_type: &ChunkType,
+    ) -> impl Iterator<Item = &ChunkRef<'a>> + '_ {
+        let chunk_type = *chunk_type;
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type() == &chunk_type)
+    }
+
+    /// The buffer the PNG was parsed from.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Copies every chunk into an owned `Png`.
+    pub fn to_png(&self) -> Png {
+        Png::from_chunks(self.chunks.iter().map(ChunkRef::to_chunk).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"first".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"last".to_vec()),
+        ];
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_png_ref_from_bytes() {
+        let bytes = testing_bytes();
+        let png = PngRef::try_from(bytes.as_ref()).unwrap();
+
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(png.chunks()[1].offset(), 8 + 17);
+        assert_eq!(
+            png.chunk_by_type(&ChunkType::from_str("LASt").unwrap())
+                .unwrap()
+                .data(),
+            b"last"
+        );
+        assert_eq!(png.to_png().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_png_ref_reports_offset() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        assert!(matches!(
+            PngRef::try_from(bytes.as_ref()),
+            Err(Error::CrcMismatch { offset: 25, .. })
+        ));
+    }
+}
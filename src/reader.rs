@@ -0,0 +1,352 @@
+use std::io::Read;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// Length and type of a chunk, read before its data.
+#[derive(Debug, Clone)]
+pub struct ChunkHeader {
+    pub offset: usize,
+    pub length: u32,
+    pub chunk_type: ChunkType,
+}
+
+/// Item yielded when iterating over a `PngReader`.
+pub enum ReadChunk {
+    /// A chunk read with its data.
+    Full(Chunk),
+    /// A chunk whose type was passed to `skip_payloads`: only its header is kept,
+    /// its data was read past after checking the CRC.
+    Skipped(ChunkHeader),
+}
+
+impl ReadChunk {
+    pub fn chunk_type(&self) -> &ChunkType {
+        match self {
+            ReadChunk::Full(chunk) => chunk.chunk_type(),
+            ReadChunk::Skipped(header) => &header.chunk_type,
+        }
+    }
+
+    /// The chunk, or `None` if its data was skipped.
+    pub fn into_chunk(self) -> Option<Chunk> {
+        match self {
+            ReadChunk::Full(chunk) => Some(chunk),
+            ReadChunk::Skipped(_) => None,
+        }
+    }
+}
+
+/// Reads a PNG one chunk at a time from any `Read` source.
+///
+/// Iterating over the reader yields every chunk in order. Chunks whose type was
+/// passed to `skip_payloads` come out as their header only, without keeping
+/// their data in memory.
+pub struct PngReader<R: Read> {
+    inner: R,
+    offset: usize,
+    pending: Option<ChunkHeader>,
+    skipped_types: Vec<ChunkType>,
+    done: bool,
+}
+
+impl<R: Read> PngReader<R> {
+    /// Reads and checks the PNG signature.
+    pub fn new(inner: R) -> Result<Self> {
+        let mut reader = PngReader {
+            inner,
+            offset: 0,
+            pending: None,
+            skipped_types: Vec::new(),
+            done: false,
+        };
+
+        let mut signature = [0u8; 8];
+        if reader.read_up_to(&mut signature)? < signature.len() {
+            return Err(Error::Truncated {
+                offset: 0,
+                needed: 8,
+            });
+        }
+        if signature != Png::STANDARD_HEADER {
+            return Err(Error::BadSignature);
+        }
+
+        Ok(reader)
+    }
+
+    /// Makes the iterator yield only the header of chunks of the given types,
+    /// without loading their data.
+    pub fn skip_payloads(mut self, chunk_types: &[ChunkType]) -> Self {
+        self.skipped_types.extend_from_slice(chunk_types);
+        self
+    }
+
+    /// Byte offset of the next unread byte, counted from the start of the file.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Reads the length and type of the next chunk, or `None` at the end of the input.
+    ///
+    /// If the data of the previous chunk was not consumed it is skipped first.
+    pub fn next_header(&mut self) -> Result<Option<ChunkHeader>> {
+        if self.pending.is_some() {
+            self.skip_data()?;
+        }
+
+        let start = self.offset;
+        let mut header = [0u8; 8];
+        match self.read_up_to(&mut header)? {
+            0 => return Ok(None),
+            8 => {}
+            _ => {
+                return Err(Error::Truncated {
+                    offset: start,
+                    needed: 12,
+                })
+            }
+        }
+
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap());
+        if length > Chunk::MAX_LENGTH {
+            return Err(Error::InvalidLength {
+                offset: start,
+                length,
+            });
+        }
+        let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&header[4..]).unwrap())?;
+
+        let chunk_header = ChunkHeader {
+            offset: start,
+            length,
+            chunk_type,
+        };
+        self.pending = Some(chunk_header.clone());
+        Ok(Some(chunk_header))
+    }
+
+    /// Reads the data and CRC of the chunk returned by the last `next_header` call.
+    pub fn read_data(&mut self) -> Result<Chunk> {
+        let header = self.take_pending()?;
+
+        // Grow the buffer as data arrives instead of trusting the declared length.
+        let mut data = Vec::new();
+        let read = (&mut self.inner)
+            .take(header.length as u64)
+            .read_to_end(&mut data)?;
+        self.offset += read;
+        if read < header.length as usize {
+            return Err(Error::Truncated {
+                offset: header.offset,
+                needed: 12 + header.length as usize,
+            });
+        }
+        let mut crc_bytes = [0u8; 4];
+        self.read_exact_at(&header, &mut crc_bytes)?;
+        let crc = u32::from_be_bytes(crc_bytes);
+
+        let chunk = Chunk::new(header.chunk_type, data);
+        if chunk.crc() != crc {
+            return Err(Error::CrcMismatch {
+                chunk_type: *chunk.chunk_type(),
+                offset: header.offset,
+                expected: crc,
+                actual: chunk.crc(),
+            });
+        }
+        Ok(chunk)
+    }
+
+    /// Discards the data of the chunk returned by the last `next_header` call,
+    /// checking its CRC, and returns the CRC.
+    pub fn skip_data(&mut self) -> Result<u32> {
+        let header = self.take_pending()?;
+
+        let crc_iso_3309 = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let mut digest = crc_iso_3309.digest();
+        digest.update(&header.chunk_type.bytes());
+
+        let mut buffer = [0u8; 8192];
+        let mut remaining = header.length as usize;
+        while remaining > 0 {
+            let size = remaining.min(buffer.len());
+            self.read_exact_at(&header, &mut buffer[..size])?;
+            digest.update(&buffer[..size]);
+            remaining -= size;
+        }
+
+        let mut crc_bytes = [0u8; 4];
+        self.read_exact_at(&header, &mut crc_bytes)?;
+        let crc = u32::from_be_bytes(crc_bytes);
+        let checksum = digest.finalize();
+        if crc != checksum {
+            return Err(Error::CrcMismatch {
+                chunk_type: header.chunk_type,
+                offset: header.offset,
+                expected: crc,
+                actual: checksum,
+            });
+        }
+        Ok(crc)
+    }
+
+    /// Reads the next full chunk, or `None` at the end of the input.
+    pub fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        match self.next_header()? {
+            Some(_) => self.read_data().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn take_pending(&mut self) -> Result<ChunkHeader> {
+        self.pending.take().ok_or(Error::Truncated {
+            offset: self.offset,
+            needed: 12,
+        })
+    }
+
+    /// Fills `buf` completely, reporting a truncated chunk otherwise.
+    fn read_exact_at(&mut self, header: &ChunkHeader, buf: &mut [u8]) -> Result<()> {
+        if self.read_up_to(buf)? < buf.len() {
+            return Err(Error::Truncated {
+                offset: header.offset,
+                needed: 12 + header.length as usize,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads until `buf` is full or the input ends, returning the number of bytes read.
+    fn read_up_to(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.inner.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.offset += filled;
+        Ok(filled)
+    }
+}
+
+impl<R: Read> Iterator for PngReader<R> {
+    type Item = Result<ReadChunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = match self.next_header() {
+            Ok(Some(header)) if self.skipped_types.contains(&header.chunk_type) => {
+                Some(self.skip_data().map(|_| ReadChunk::Skipped(header)))
+            }
+            Ok(Some(_)) => Some(self.read_data().map(ReadChunk::Full)),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        };
+
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"first".to_vec()),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![7; 20000]),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"last".to_vec()),
+        ];
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_reads_chunks_in_order() {
+        let bytes = testing_bytes();
+        let reader = PngReader::new(bytes.as_slice()).unwrap();
+        let types: Vec<String> = reader
+            .map(|chunk| {
+                chunk
+                    .unwrap()
+                    .into_chunk()
+                    .unwrap()
+                    .chunk_type()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(types, vec!["FrSt", "IDAT", "LASt"]);
+    }
+
+    #[test]
+    fn test_skip_payloads() {
+        let bytes = testing_bytes();
+        let reader = PngReader::new(bytes.as_slice())
+            .unwrap()
+            .skip_payloads(&[ChunkType::from_str("IDAT").unwrap()]);
+        let chunks: Vec<ReadChunk> = reader.map(|chunk| chunk.unwrap()).collect();
+        let types: Vec<String> = chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["FrSt", "IDAT", "LASt"]);
+
+        match &chunks[1] {
+            ReadChunk::Skipped(header) => {
+                assert_eq!(header.offset, 8 + 17);
+                assert_eq!(header.length, 20000);
+            }
+            ReadChunk::Full(_) => panic!("IDAT data should be skipped"),
+        }
+        assert!(matches!(&chunks[2], ReadChunk::Full(chunk) if chunk.data() == b"last"));
+    }
+
+    #[test]
+    fn test_headers_report_offsets() {
+        let bytes = testing_bytes();
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+        let mut offsets = Vec::new();
+        while let Some(header) = reader.next_header().unwrap() {
+            offsets.push(header.offset);
+        }
+        assert_eq!(offsets, vec![8, 8 + 17, 8 + 17 + 20012]);
+    }
+
+    #[test]
+    fn test_skipped_chunk_crc_is_checked() {
+        let mut bytes = testing_bytes();
+        bytes[8 + 17 + 100] ^= 1;
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+        reader.next_header().unwrap();
+        reader.next_header().unwrap();
+        assert!(matches!(
+            reader.skip_data(),
+            Err(Error::CrcMismatch { offset: 25, .. })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        let bytes = testing_bytes();
+        let mut reader = PngReader::new(&bytes[..bytes.len() - 2]).unwrap();
+        let last = reader.by_ref().last().unwrap();
+        assert!(matches!(last, Err(Error::Truncated { offset: 20037, .. })));
+        assert!(reader.next().is_none());
+    }
+}
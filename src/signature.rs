@@ -0,0 +1,324 @@
+use std::str::FromStr;
+
+use ed25519_dalek::{Signature, Signer};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::keys::{SigningKey, VerifyingKey};
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// Ancillary, private, safe-to-copy chunk holding the signature of another chunk.
+pub const SIGNATURE_CHUNK_TYPE: &str = "siGn";
+
+/// Ancillary, private, safe-to-copy chunk holding the integrity seal of the whole image.
+pub const SEAL_CHUNK_TYPE: &str = "seAl";
+
+const MAGIC: [u8; 3] = *b"PMs";
+const VERSION: u8 = 1;
+const DOMAIN: &[u8] = b"pngme-signature-v1\0";
+const DATA_LEN: usize = MAGIC.len() + 1 + 4 + 32 + 64;
+
+const SEAL_MAGIC: [u8; 3] = *b"PMi";
+const SEAL_DOMAIN: &[u8] = b"pngme-seal-v1\0";
+
+/// A parsed signature chunk.
+pub struct ChunkSignature {
+    signed_type: ChunkType,
+    signer: VerifyingKey,
+    signature: Signature,
+}
+
+impl ChunkSignature {
+    pub fn parse(data: &[u8]) -> Result<ChunkSignature> {
+        if data.len() != DATA_LEN || !data.starts_with(&MAGIC) {
+            return Err(Error::InvalidPayload("assinatura mal formada"));
+        }
+        let version = data[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let fields = &data[MAGIC.len() + 1..];
+        let signed_type = ChunkType::try_from(<[u8; 4]>::try_from(&fields[..4]).unwrap())?;
+        let signer = VerifyingKey::from_bytes(&fields[4..36].try_into().unwrap())?;
+        let signature = Signature::from_bytes(&fields[36..].try_into().unwrap());
+        Ok(ChunkSignature {
+            signed_type,
+            signer,
+            signature,
+        })
+    }
+
+    pub fn signed_type(&self) -> &ChunkType {
+        &self.signed_type
+    }
+
+    pub fn signer(&self) -> &VerifyingKey {
+        &self.signer
+    }
+
+    /// Checks the signature against the type and data of `chunk`.
+    pub fn verify(&self, chunk: &Chunk) -> Result<()> {
+        if chunk.chunk_type() != &self.signed_type {
+            return Err(Error::SignatureInvalid);
+        }
+        self.signer
+            .key()
+            .verify_strict(&signed_message(chunk), &self.signature)
+            .map_err(|_| Error::SignatureInvalid)
+    }
+}
+
+/// Builds the companion chunk carrying the signature of `chunk` by `signing_key`.
+pub fn sign_chunk(signing_key: &SigningKey, chunk: &Chunk) -> Chunk {
+    let signature = signing_key.key().sign(&signed_message(chunk));
+
+    let mut data = Vec::with_capacity(DATA_LEN);
+    data.extend_from_slice(&MAGIC);
+    data.push(VERSION);
+    data.extend_from_slice(&chunk.chunk_type().bytes());
+    data.extend_from_slice(signing_key.verifying_key().as_bytes());
+    data.extend_from_slice(&signature.to_bytes());
+
+    Chunk::new(ChunkType::from_str(SIGNATURE_CHUNK_TYPE).unwrap(), data)
+}
+
+/// Verifies the first chunk of `chunk_type` in `png` against its signature chunks.
+///
+/// When `trusted` is given only signatures from that key are accepted. Returns
+/// the key of the signer whose signature matched.
+pub fn verify_chunk(
+    png: &Png,
+    chunk_type: &ChunkType,
+    trusted: Option<&VerifyingKey>,
+) -> Result<VerifyingKey> {
+    let chunk = png
+        .chunk_by_type(chunk_type)
+        .ok_or_else(|| Error::ChunkNotFound(chunk_type.to_string()))?;
+
+    let signature_type = ChunkType::from_str(SIGNATURE_CHUNK_TYPE).unwrap();
+    let signatures: Vec<ChunkSignature> = png
+        .chunks_by_type(&signature_type)
+        .filter_map(|chunk| ChunkSignature::parse(chunk.data()).ok())
+        .filter(|signature| signature.signed_type() == chunk.chunk_type())
+        .collect();
+    if signatures.is_empty() {
+        return Err(Error::SignatureNotFound(chunk_type.to_string()));
+    }
+
+    let mut untrusted = None;
+    for signature in &signatures {
+        if signature.verify(chunk).is_err() {
+            continue;
+        }
+        match trusted {
+            Some(key) if key != signature.signer() => untrusted = Some(signature.signer()),
+            _ => return Ok(signature.signer().clone()),
+        }
+    }
+
+    match untrusted {
+        Some(signer) => Err(Error::UntrustedSigner(signer.fingerprint())),
+        None => Err(Error::SignatureInvalid),
+    }
+}
+
+/// Signs the integrity digest of `png` and stores it in a seal chunk, replacing any previous seal.
+///
+/// The seal covers the critical chunks and the ancillary chunks listed in `extra`,
+/// so other metadata can be added or removed without breaking it.
+pub fn seal_png(png: &mut Png, signing_key: &SigningKey, extra: &[ChunkType]) -> Result<()> {
+    if extra.len() > u8::MAX as usize {
+        return Err(Error::InvalidPayload("tipos demais no selo"));
+    }
+    let seal_type = ChunkType::from_str(SEAL_CHUNK_TYPE).unwrap();
+    png.remove_chunks_by_type(&seal_type);
+
+    let digest = png.integrity_digest(extra);
+    let signature = signing_key.key().sign(&sealed_message(extra, &digest));
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&SEAL_MAGIC);
+    data.push(VERSION);
+    data.push(extra.len() as u8);
+    for chunk_type in extra {
+        data.extend_from_slice(&chunk_type.bytes());
+    }
+    data.extend_from_slice(signing_key.verifying_key().as_bytes());
+    data.extend_from_slice(&signature.to_bytes());
+
+    png.append_chunk(Chunk::new(seal_type, data));
+    Ok(())
+}
+
+/// Checks the seal chunk of `png` against the current image, returning the signer's key.
+pub fn check_png_seal(png: &Png, trusted: Option<&VerifyingKey>) -> Result<VerifyingKey> {
+    let seal = png
+        .chunk_by_type(&ChunkType::from_str(SEAL_CHUNK_TYPE).unwrap())
+        .ok_or_else(|| Error::SignatureNotFound(SEAL_CHUNK_TYPE.to_string()))?;
+    let data = seal.data();
+
+    let header_len = SEAL_MAGIC.len() + 2;
+    if data.len() < header_len || !data.starts_with(&SEAL_MAGIC) {
+        return Err(Error::InvalidPayload("selo mal formado"));
+    }
+    let version = data[SEAL_MAGIC.len()];
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    let count = data[header_len - 1] as usize;
+    let keys_start = header_len + count * 4;
+    if data.len() != keys_start + 32 + 64 {
+        return Err(Error::InvalidPayload("selo mal formado"));
+    }
+
+    let extra = data[header_len..keys_start]
+        .chunks(4)
+        .map(|bytes| ChunkType::try_from(<[u8; 4]>::try_from(bytes).unwrap()))
+        .collect::<Result<Vec<ChunkType>>>()?;
+    let signer = VerifyingKey::from_bytes(&data[keys_start..keys_start + 32].try_into().unwrap())?;
+    let signature = Signature::from_bytes(&data[keys_start + 32..].try_into().unwrap());
+
+    let digest = png.integrity_digest(&extra);
+    signer
+        .key()
+        .verify_strict(&sealed_message(&extra, &digest), &signature)
+        .map_err(|_| Error::SignatureInvalid)?;
+
+    match trusted {
+        Some(key) if key != &signer => Err(Error::UntrustedSigner(signer.fingerprint())),
+        _ => Ok(signer),
+    }
+}
+
+/// The covered types are signed along with the digest so they cannot be swapped.
+fn sealed_message(extra: &[ChunkType], digest: &[u8; 32]) -> Vec<u8> {
+    let mut message = SEAL_DOMAIN.to_vec();
+    for chunk_type in extra {
+        message.extend_from_slice(&chunk_type.bytes());
+    }
+    message.extend_from_slice(digest);
+    message
+}
+
+fn signed_message(chunk: &Chunk) -> Vec<u8> {
+    DOMAIN
+        .iter()
+        .chain(chunk.chunk_type().bytes().iter())
+        .chain(chunk.data().iter())
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_png(signing_key: &SigningKey) -> Png {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"signed".to_vec());
+        let signature = sign_chunk(signing_key, &chunk);
+        Png::from_chunks(vec![chunk, signature])
+    }
+
+    #[test]
+    fn test_verify_signed_chunk() {
+        let signing_key = SigningKey::generate();
+        let png = signed_png(&signing_key);
+
+        let signer = verify_chunk(&png, &ChunkType::from_str("ruSt").unwrap(), None).unwrap();
+        assert_eq!(signer, signing_key.verifying_key());
+        let trusted = signing_key.verifying_key();
+        assert!(verify_chunk(&png, &ChunkType::from_str("ruSt").unwrap(), Some(&trusted)).is_ok());
+    }
+
+    #[test]
+    fn test_verify_untrusted_signer() {
+        let png = signed_png(&SigningKey::generate());
+        let other = SigningKey::generate().verifying_key();
+        assert!(matches!(
+            verify_chunk(&png, &ChunkType::from_str("ruSt").unwrap(), Some(&other)),
+            Err(Error::UntrustedSigner(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_altered_chunk() {
+        let signing_key = SigningKey::generate();
+        let signature = signed_png(&signing_key)
+            .remove_chunk(&ChunkType::from_str(SIGNATURE_CHUNK_TYPE).unwrap())
+            .unwrap();
+        let altered = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"altered".to_vec());
+        let png = Png::from_chunks(vec![altered, signature]);
+
+        assert!(matches!(
+            verify_chunk(&png, &ChunkType::from_str("ruSt").unwrap(), None),
+            Err(Error::SignatureInvalid)
+        ));
+    }
+
+    fn testing_image() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), b"pixels".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_seal_survives_unrelated_metadata() {
+        let signing_key = SigningKey::generate();
+        let mut png = testing_image();
+        seal_png(&mut png, &signing_key, &[]).unwrap();
+
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"Comment\0added later".to_vec(),
+        ));
+        let signer = check_png_seal(&png, Some(&signing_key.verifying_key())).unwrap();
+        assert_eq!(signer, signing_key.verifying_key());
+    }
+
+    #[test]
+    fn test_seal_detects_tampering() {
+        let signing_key = SigningKey::generate();
+        let text_type = ChunkType::from_str("tEXt").unwrap();
+        let mut png = testing_image();
+        png.append_chunk(Chunk::new(text_type, b"Author\0me".to_vec()));
+        seal_png(&mut png, &signing_key, &[text_type]).unwrap();
+        let sealed_bytes = png.as_bytes();
+
+        let mut altered_pixels = Png::try_from(sealed_bytes.as_ref()).unwrap();
+        altered_pixels
+            .remove_chunk(&ChunkType::from_str("IDAT").unwrap())
+            .unwrap();
+        altered_pixels.append_chunk(Chunk::new(
+            ChunkType::from_str("IDAT").unwrap(),
+            b"other".to_vec(),
+        ));
+        assert!(matches!(
+            check_png_seal(&altered_pixels, None),
+            Err(Error::SignatureInvalid)
+        ));
+
+        let mut removed_text = Png::try_from(sealed_bytes.as_ref()).unwrap();
+        removed_text
+            .remove_chunk(&ChunkType::from_str("tEXt").unwrap())
+            .unwrap();
+        assert!(matches!(
+            check_png_seal(&removed_text, None),
+            Err(Error::SignatureInvalid)
+        ));
+    }
+
+    #[test]
+    fn test_verify_missing_signature() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"plain".to_vec());
+        let png = Png::from_chunks(vec![chunk]);
+        assert!(matches!(
+            verify_chunk(&png, &ChunkType::from_str("ruSt").unwrap(), None),
+            Err(Error::SignatureNotFound(_))
+        ));
+    }
+}
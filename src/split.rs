@@ -0,0 +1,226 @@
+use sha2::{Digest, Sha256};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::envelope::{self, Envelope};
+use crate::{Error, Result};
+
+/// Starts the body of an envelope holding one piece of a split payload.
+const MAGIC: [u8; 3] = *b"PMp";
+const VERSION: u8 = 1;
+/// Payloads larger than this are split when no maximum is given.
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 1 << 20;
+/// `magic | version | sequence | total | sha256 of the whole payload`
+const BODY_HEADER_LEN: usize = MAGIC.len() + 1 + 4 + 4 + 32;
+/// Bytes of chunk data each piece spends on headers, envelope included.
+pub const PIECE_HEADER_LEN: usize = envelope::HEADER_LEN + BODY_HEADER_LEN;
+
+/// The envelope body of chunk data written by `split_payload`, if it is a piece.
+fn piece_body(data: &[u8]) -> Option<&[u8]> {
+    match Envelope::open(data) {
+        Ok(Some((envelope, body))) if envelope.piece => Some(body),
+        _ => None,
+    }
+}
+
+/// Returns true if `data` is one of the pieces written by `split_payload`.
+pub fn is_piece(data: &[u8]) -> bool {
+    piece_body(data).is_some()
+}
+
+/// The payload hash recorded in a piece; pieces of the same payload share it.
+pub fn piece_hash(data: &[u8]) -> Option<&[u8]> {
+    piece_body(data)
+        .filter(|body| body.starts_with(&MAGIC) && body.len() >= BODY_HEADER_LEN)
+        .map(|body| &body[12..BODY_HEADER_LEN])
+}
+
+/// Splits `payload` into chunks of `chunk_type` whose data is at most `max_chunk_size` bytes.
+///
+/// Every piece records its sequence number, the number of pieces and the
+/// SHA-256 of the whole payload, so `reassemble` can order and validate them.
+pub fn split_payload(
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    max_chunk_size: usize,
+) -> Result<Vec<Chunk>> {
+    let max_chunk_size = max_chunk_size.min(Chunk::MAX_LENGTH as usize);
+    if max_chunk_size <= PIECE_HEADER_LEN {
+        return Err(Error::InvalidPayload(
+            "tamanho maximo de chunk pequeno demais",
+        ));
+    }
+    let piece_size = max_chunk_size - PIECE_HEADER_LEN;
+    let total = payload.len().div_ceil(piece_size).max(1);
+    let total = u32::try_from(total).map_err(|_| Error::InvalidPayload("pedacos demais"))?;
+    let hash = Sha256::digest(payload);
+
+    let pieces: Vec<&[u8]> = if payload.is_empty() {
+        vec![payload]
+    } else {
+        payload.chunks(piece_size).collect()
+    };
+    pieces
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, piece)| {
+            let mut body = Vec::with_capacity(BODY_HEADER_LEN + piece.len());
+            body.extend_from_slice(&MAGIC);
+            body.push(VERSION);
+            body.extend_from_slice(&(sequence as u32).to_be_bytes());
+            body.extend_from_slice(&total.to_be_bytes());
+            body.extend_from_slice(&hash);
+            body.extend_from_slice(piece);
+            let envelope = Envelope {
+                piece: true,
+                ..Envelope::default()
+            };
+            Chunk::try_new(*chunk_type, envelope.wrap(&body))
+        })
+        .collect()
+}
+
+/// Orders the pieces by sequence number, checks that none is missing or
+/// duplicated and that the result matches the recorded hash.
+///
+/// Only pieces carrying the same hash as the first one are considered, so
+/// other payloads stored under the same chunk type are ignored.
+pub fn reassemble<'a, I>(pieces: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut expected: Option<(u32, &[u8])> = None;
+    let mut matching: Vec<(u32, &[u8])> = Vec::new();
+
+    for data in pieces {
+        let Some(data) = piece_body(data) else {
+            continue;
+        };
+        if !data.starts_with(&MAGIC) || data.len() < BODY_HEADER_LEN {
+            return Err(Error::InvalidPayload("pedaco mal formado"));
+        }
+        let version = data[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let sequence = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        let total = u32::from_be_bytes(data[8..12].try_into().unwrap());
+        let hash = &data[12..BODY_HEADER_LEN];
+
+        let (expected_total, expected_hash) = *expected.get_or_insert((total, hash));
+        if hash != expected_hash {
+            continue;
+        }
+        if total != expected_total || sequence >= total {
+            return Err(Error::InvalidPayload("numeracao de pedacos inconsistente"));
+        }
+        matching.push((sequence, &data[BODY_HEADER_LEN..]));
+    }
+
+    let (total, hash) = expected.ok_or(Error::InvalidPayload("nenhum pedaco encontrado"))?;
+
+    // Walk the pieces in order rather than sizing anything by `total`, which
+    // comes from the file and may be far larger than the pieces present.
+    matching.sort_by_key(|(sequence, _)| *sequence);
+    let mut payload = Vec::new();
+    let mut next = 0;
+    for (sequence, piece) in matching {
+        if sequence < next {
+            return Err(Error::DuplicatePiece(sequence));
+        }
+        if sequence > next {
+            return Err(Error::MissingPiece(next));
+        }
+        payload.extend_from_slice(piece);
+        next += 1;
+    }
+    if next < total {
+        return Err(Error::MissingPiece(next));
+    }
+
+    if Sha256::digest(&payload).as_slice() != hash {
+        return Err(Error::InvalidPayload("hash do conteudo nao confere"));
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_pieces() -> (Vec<u8>, Vec<Chunk>) {
+        let payload: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let chunks = split_payload(&chunk_type, &payload, PIECE_HEADER_LEN + 300).unwrap();
+        (payload, chunks)
+    }
+
+    #[test]
+    fn test_split_and_reassemble() {
+        let (payload, chunks) = testing_pieces();
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.length() as usize <= PIECE_HEADER_LEN + 300));
+
+        let reversed: Vec<&[u8]> = chunks.iter().rev().map(|chunk| chunk.data()).collect();
+        assert_eq!(reassemble(reversed).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_missing_piece() {
+        let (_, chunks) = testing_pieces();
+        let pieces = chunks
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != 2)
+            .map(|(_, chunk)| chunk.data());
+        assert!(matches!(reassemble(pieces), Err(Error::MissingPiece(2))));
+    }
+
+    #[test]
+    fn test_duplicate_piece() {
+        let (_, chunks) = testing_pieces();
+        let pieces = chunks
+            .iter()
+            .chain(chunks.iter().skip(1).take(1))
+            .map(|chunk| chunk.data());
+        assert!(matches!(reassemble(pieces), Err(Error::DuplicatePiece(1))));
+    }
+
+    #[test]
+    fn test_huge_total() {
+        // A lone piece claiming 2^32 - 1 pieces must not size anything by that count.
+        let (_, chunks) = testing_pieces();
+        let mut data = chunks[0].data().to_vec();
+        let total = envelope::HEADER_LEN + 8;
+        data[total..total + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            reassemble([&data[..]]),
+            Err(Error::MissingPiece(1))
+        ));
+    }
+
+    #[test]
+    fn test_other_chunks_skipped() {
+        let (payload, chunks) = testing_pieces();
+        let pieces =
+            std::iter::once(&b"not a piece"[..]).chain(chunks.iter().map(|chunk| chunk.data()));
+        assert_eq!(reassemble(pieces).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let chunks = split_payload(&chunk_type, &[], 100).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert!(reassemble([chunks[0].data()]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_max_chunk_size_too_small() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        assert!(split_payload(&chunk_type, b"data", PIECE_HEADER_LEN).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+use crate::chunk::{latin1_to_string, Chunk};
+use crate::chunk_type::ChunkType;
+use crate::compression::{self, DEFAULT_MAX_INFLATED_SIZE};
+use crate::{Error, Result};
+
+/// The only compression method defined for zTXt and iTXt (zlib).
+const COMPRESSION_METHOD_DEFLATE: u8 = 0;
+
+/// A `tEXt` chunk: Latin-1 keyword and text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    pub keyword: String,
+    pub text: String,
+}
+
+/// A `zTXt` chunk: Latin-1 keyword and zlib-compressed Latin-1 text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedTextChunk {
+    pub keyword: String,
+    pub text: String,
+}
+
+/// An `iTXt` chunk: Latin-1 keyword, UTF-8 text, optionally compressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+    pub keyword: String,
+    pub compressed: bool,
+    /// RFC 3066 language tag, empty if unknown.
+    pub language_tag: String,
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+/// Any of the three textual chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextEntry {
+    Text(TextChunk),
+    Compressed(CompressedTextChunk),
+    International(InternationalTextChunk),
+}
+
+/// Checks the rules of the PNG spec: 1 to 79 printable Latin-1 characters,
+/// with no leading, trailing or consecutive spaces.
+pub fn validate_keyword(keyword: &str) -> Result<()> {
+    let invalid = || Error::InvalidKeyword(keyword.to_string());
+    let length = keyword.chars().count();
+    if !(1..=79).contains(&length) {
+        return Err(invalid());
+    }
+    if !keyword
+        .chars()
+        .all(|c| matches!(c as u32, 32..=126 | 161..=255))
+    {
+        return Err(invalid());
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// Returns true if `chunk_type` is tEXt, zTXt or iTXt.
+pub fn is_text_chunk_type(chunk_type: &ChunkType) -> bool {
+    matches!(
+        *chunk_type,
+        ChunkType::TEXT | ChunkType::ZTXT | ChunkType::ITXT
+    )
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        expect_type(chunk, ChunkType::TEXT)?;
+        let (keyword, text) = split_keyword(chunk.data())?;
+        Ok(TextChunk {
+            keyword,
+            text: latin1_to_string(text),
+        })
+    }
+}
+
+impl TextChunk {
+    pub fn new(keyword: &str, text: &str) -> TextChunk {
+        TextChunk {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    /// Fails if the keyword is invalid or the text has characters outside Latin-1.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data = keyword_bytes(&self.keyword)?;
+        data.extend(string_to_latin1(&self.text)?);
+        Chunk::try_new(ChunkType::TEXT, data)
+    }
+}
+
+impl TryFrom<&Chunk> for CompressedTextChunk {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        expect_type(chunk, ChunkType::ZTXT)?;
+        let (keyword, rest) = split_keyword(chunk.data())?;
+        let (&method, compressed) = rest
+            .split_first()
+            .ok_or(Error::InvalidPayload("chunk de texto incompleto"))?;
+        expect_deflate(method)?;
+        let text = compression::inflate(compressed, DEFAULT_MAX_INFLATED_SIZE)?;
+        Ok(CompressedTextChunk {
+            keyword,
+            text: latin1_to_string(&text),
+        })
+    }
+}
+
+impl CompressedTextChunk {
+    pub fn new(keyword: &str, text: &str) -> CompressedTextChunk {
+        CompressedTextChunk {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data = keyword_bytes(&self.keyword)?;
+        data.push(COMPRESSION_METHOD_DEFLATE);
+        data.extend(compression::deflate(&string_to_latin1(&self.text)?)?);
+        Chunk::try_new(ChunkType::ZTXT, data)
+    }
+}
+
+impl TryFrom<&Chunk> for InternationalTextChunk {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        expect_type(chunk, ChunkType::ITXT)?;
+        let (keyword, rest) = split_keyword(chunk.data())?;
+        if rest.len() < 2 {
+            return Err(Error::InvalidPayload("chunk de texto incompleto"));
+        }
+        let compressed = match rest[0] {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::InvalidPayload("flag de compressao invalida")),
+        };
+        if compressed {
+            expect_deflate(rest[1])?;
+        }
+
+        let mut fields = rest[2..].splitn(3, |&byte| byte == 0);
+        let language_tag = fields.next().unwrap_or_default();
+        let translated_keyword = fields
+            .next()
+            .ok_or(Error::InvalidPayload("chunk de texto incompleto"))?;
+        let text = fields
+            .next()
+            .ok_or(Error::InvalidPayload("chunk de texto incompleto"))?;
+        let text = if compressed {
+            compression::inflate(text, DEFAULT_MAX_INFLATED_SIZE)?
+        } else {
+            text.to_vec()
+        };
+
+        Ok(InternationalTextChunk {
+            keyword,
+            compressed,
+            language_tag: utf8_field(language_tag)?,
+            translated_keyword: utf8_field(translated_keyword)?,
+            text: String::from_utf8(text).map_err(|err| Error::InvalidUtf8(err.utf8_error()))?,
+        })
+    }
+}
+
+impl InternationalTextChunk {
+    pub fn new(keyword: &str, text: &str) -> InternationalTextChunk {
+        InternationalTextChunk {
+            keyword: keyword.to_string(),
+            compressed: false,
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        if self.language_tag.contains('\0') || self.translated_keyword.contains('\0') {
+            return Err(Error::InvalidPayload("campo de texto contem byte nulo"));
+        }
+        let mut data = keyword_bytes(&self.keyword)?;
+        data.push(self.compressed as u8);
+        data.push(COMPRESSION_METHOD_DEFLATE);
+        data.extend_from_slice(self.language_tag.as_bytes());
+        data.push(0);
+        data.extend_from_slice(self.translated_keyword.as_bytes());
+        data.push(0);
+        if self.compressed {
+            data.extend(compression::deflate(self.text.as_bytes())?);
+        } else {
+            data.extend_from_slice(self.text.as_bytes());
+        }
+        Chunk::try_new(ChunkType::ITXT, data)
+    }
+}
+
+impl TryFrom<&Chunk> for TextEntry {
+    type Error = Error;
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        match *chunk.chunk_type() {
+            ChunkType::TEXT => TextChunk::try_from(chunk).map(TextEntry::Text),
+            ChunkType::ZTXT => CompressedTextChunk::try_from(chunk).map(TextEntry::Compressed),
+            ChunkType::ITXT => {
+                InternationalTextChunk::try_from(chunk).map(TextEntry::International)
+            }
+            _ => Err(Error::InvalidPayload("o chunk nao e de texto")),
+        }
+    }
+}
+
+impl TextEntry {
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextEntry::Text(chunk) => &chunk.keyword,
+            TextEntry::Compressed(chunk) => &chunk.keyword,
+            TextEntry::International(chunk) => &chunk.keyword,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            TextEntry::Text(chunk) => &chunk.text,
+            TextEntry::Compressed(chunk) => &chunk.text,
+            TextEntry::International(chunk) => &chunk.text,
+        }
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        match self {
+            TextEntry::Text(chunk) => chunk.to_chunk(),
+            TextEntry::Compressed(chunk) => chunk.to_chunk(),
+            TextEntry::International(chunk) => chunk.to_chunk(),
+        }
+    }
+}
+
+fn expect_type(chunk: &Chunk, chunk_type: ChunkType) -> Result<()> {
+    if *chunk.chunk_type() != chunk_type {
+        return Err(Error::InvalidPayload("tipo de chunk de texto inesperado"));
+    }
+    Ok(())
+}
+
+fn expect_deflate(method: u8) -> Result<()> {
+    if method != COMPRESSION_METHOD_DEFLATE {
+        return Err(Error::InvalidPayload("metodo de compressao desconhecido"));
+    }
+    Ok(())
+}
+
+/// Splits `keyword \0 rest`, validating the keyword.
+fn split_keyword(data: &[u8]) -> Result<(String, &[u8])> {
+    let separator = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(Error::InvalidPayload("chunk de texto sem separador"))?;
+    let keyword = latin1_to_string(&data[..separator]);
+    validate_keyword(&keyword)?;
+    Ok((keyword, &data[separator + 1..]))
+}
+
+/// The keyword in Latin-1 followed by its null separator.
+fn keyword_bytes(keyword: &str) -> Result<Vec<u8>> {
+    validate_keyword(keyword)?;
+    let mut bytes = string_to_latin1(keyword)?;
+    bytes.push(0);
+    Ok(bytes)
+}
+
+fn string_to_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c as u32))
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| Error::InvalidPayload("texto fora do Latin-1"))
+}
+
+fn utf8_field(bytes: &[u8]) -> Result<String> {
+    std::str::from_utf8(bytes)
+        .map(str::to_string)
+        .map_err(Error::InvalidUtf8)
+}
+
+/// The keyword of a textual chunk, without decoding (or inflating) its text.
+pub(crate) fn keyword_of(chunk: &Chunk) -> Option<String> {
+    if !is_text_chunk_type(chunk.chunk_type()) {
+        return None;
+    }
+    split_keyword(chunk.data()).ok().map(|(keyword, _)| keyword)
+}
+
+/// Whether `text` can be stored in a tEXt chunk.
+pub(crate) fn is_latin1(text: &str) -> bool {
+    text.chars().all(|c| (c as u32) <= 0xff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_text_chunk_round_trip() {
+        let text = TextChunk::new("Author", "José");
+        let chunk = text.to_chunk().unwrap();
+        assert_eq!(chunk.data(), b"Author\0Jos\xe9");
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_text_chunk_rejects_non_latin1() {
+        assert!(TextChunk::new("Title", "日本").to_chunk().is_err());
+    }
+
+    #[test]
+    fn test_compressed_text_chunk_round_trip() {
+        let text = CompressedTextChunk::new("Description", &"a long description ".repeat(50));
+        let chunk = text.to_chunk().unwrap();
+        assert!(chunk.data().len() < text.text.len());
+        assert_eq!(CompressedTextChunk::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_international_text_chunk_round_trip() {
+        for compressed in [false, true] {
+            let text = InternationalTextChunk {
+                keyword: "Title".to_string(),
+                compressed,
+                language_tag: "ja".to_string(),
+                translated_keyword: "タイトル".to_string(),
+                text: "日本語のテキスト".to_string(),
+            };
+            let chunk = text.to_chunk().unwrap();
+            assert_eq!(InternationalTextChunk::try_from(&chunk).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn test_validate_keyword() {
+        assert!(validate_keyword("Creation Time").is_ok());
+        assert!(validate_keyword("Café").is_ok());
+        assert!(validate_keyword("").is_err());
+        assert!(validate_keyword(&"k".repeat(80)).is_err());
+        assert!(validate_keyword(" Title").is_err());
+        assert!(validate_keyword("Title ").is_err());
+        assert!(validate_keyword("Two  spaces").is_err());
+        assert!(validate_keyword("Tab\there").is_err());
+        assert!(validate_keyword("日本").is_err());
+    }
+
+    #[test]
+    fn test_malformed_text_chunks() {
+        let chunk =
+            |name: &str, data: &[u8]| Chunk::new(ChunkType::from_str(name).unwrap(), data.to_vec());
+        assert!(TextEntry::try_from(&chunk("tEXt", b"no separator")).is_err());
+        assert!(TextEntry::try_from(&chunk("zTXt", b"Key\0\x01junk")).is_err());
+        assert!(TextEntry::try_from(&chunk("zTXt", b"Key\0\0not zlib")).is_err());
+        assert!(TextEntry::try_from(&chunk("iTXt", b"Key\0\x00\x00en")).is_err());
+        assert!(TextEntry::try_from(&chunk("iTXt", b"Key\0\x02\x00\0\0text")).is_err());
+        assert!(TextEntry::try_from(&chunk("iTXt", b"Key\0\0\0\0\0\xff")).is_err());
+        assert!(TextEntry::try_from(&chunk("RuSt", b"Key\0text")).is_err());
+    }
+}
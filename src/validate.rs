@@ -0,0 +1,353 @@
+use std::fmt::Display;
+
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+
+/// Ancillary chunks that may appear at most once.
+const UNIQUE_CHUNKS: [ChunkType; 11] = [
+    ChunkType::CHRM,
+    ChunkType::GAMA,
+    ChunkType::ICCP,
+    ChunkType::SBIT,
+    ChunkType::SRGB,
+    ChunkType::BKGD,
+    ChunkType::HIST,
+    ChunkType::TRNS,
+    ChunkType::PHYS,
+    ChunkType::TIME,
+    ChunkType::EXIF,
+];
+/// Chunks that must come before PLTE and IDAT.
+const BEFORE_PLTE: [ChunkType; 5] = [
+    ChunkType::CHRM,
+    ChunkType::GAMA,
+    ChunkType::ICCP,
+    ChunkType::SBIT,
+    ChunkType::SRGB,
+];
+/// Chunks that must come after PLTE (if any) and before IDAT.
+const AFTER_PLTE: [ChunkType; 3] = [ChunkType::TRNS, ChunkType::BKGD, ChunkType::HIST];
+/// Chunks that must come before IDAT, with no constraint relative to PLTE.
+const BEFORE_IDAT: [ChunkType; 2] = [ChunkType::PHYS, ChunkType::SPLT];
+const CRITICAL_CHUNKS: [ChunkType; 4] = [
+    ChunkType::IHDR,
+    ChunkType::PLTE,
+    ChunkType::IDAT,
+    ChunkType::IEND,
+];
+
+/// A broken rule of the PNG chunk layout. `index` is the position of the offending chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The first chunk is not IHDR.
+    HeaderNotFirst,
+    /// The IHDR data is malformed or describes an impossible image.
+    InvalidHeader(&'static str),
+    /// The last chunk is not IEND.
+    EndNotLast,
+    /// There is no IDAT chunk.
+    MissingImageData,
+    /// An IDAT chunk is separated from the previous one by other chunks.
+    NonContiguousImageData { index: usize },
+    /// The color type is indexed and there is no PLTE chunk.
+    MissingPalette,
+    /// PLTE appears in a grayscale image.
+    UnexpectedPalette { index: usize },
+    /// A chunk that must be unique appears again.
+    DuplicateChunk { chunk_type: ChunkType, index: usize },
+    /// A chunk appears on the wrong side of PLTE or IDAT.
+    MisplacedChunk {
+        chunk_type: ChunkType,
+        index: usize,
+        rule: &'static str,
+    },
+    /// hIST without a PLTE chunk.
+    HistogramWithoutPalette { index: usize },
+    /// A critical chunk this tool (and most decoders) does not know how to handle.
+    UnknownCriticalChunk { chunk_type: ChunkType, index: usize },
+}
+
+impl Diagnostic {
+    /// The chunk the diagnostic points at, if it is about a single chunk.
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            Diagnostic::NonContiguousImageData { index }
+            | Diagnostic::UnexpectedPalette { index }
+            | Diagnostic::DuplicateChunk { index, .. }
+            | Diagnostic::MisplacedChunk { index, .. }
+            | Diagnostic::HistogramWithoutPalette { index }
+            | Diagnostic::UnknownCriticalChunk { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// Whether the problem is about where chunks are, rather than which chunks there are.
+    pub fn is_ordering_problem(&self) -> bool {
+        matches!(
+            self,
+            Diagnostic::HeaderNotFirst
+                | Diagnostic::EndNotLast
+                | Diagnostic::NonContiguousImageData { .. }
+                | Diagnostic::MisplacedChunk { .. }
+        )
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::HeaderNotFirst => write!(f, "IHDR must be the first chunk"),
+            Diagnostic::InvalidHeader(reason) => write!(f, "invalid IHDR: {}", reason),
+            Diagnostic::EndNotLast => write!(f, "IEND must be the last chunk"),
+            Diagnostic::MissingImageData => write!(f, "no IDAT chunk"),
+            Diagnostic::NonContiguousImageData { index } => {
+                write!(f, "chunk {}: IDAT chunks must be consecutive", index)
+            }
+            Diagnostic::MissingPalette => write!(f, "indexed-color image without PLTE"),
+            Diagnostic::UnexpectedPalette { index } => {
+                write!(
+                    f,
+                    "chunk {}: PLTE is not allowed in grayscale images",
+                    index
+                )
+            }
+            Diagnostic::DuplicateChunk { chunk_type, index } => {
+                write!(f, "chunk {}: {} may appear only once", index, chunk_type)
+            }
+            Diagnostic::MisplacedChunk {
+                chunk_type,
+                index,
+                rule,
+            } => write!(f, "chunk {}: {} must come {}", index, chunk_type, rule),
+            Diagnostic::HistogramWithoutPalette { index } => {
+                write!(f, "chunk {}: hIST requires a PLTE chunk", index)
+            }
+            Diagnostic::UnknownCriticalChunk { chunk_type, index } => {
+                write!(f, "chunk {}: unknown critical chunk {}", index, chunk_type)
+            }
+        }
+    }
+}
+
+/// Checks the chunk layout rules of the PNG spec over `(type, data)` pairs in file order.
+/// Returns every broken rule; an empty list means the layout is valid.
+pub fn validate_chunks<'a, I>(chunks: I) -> Vec<Diagnostic>
+where
+    I: IntoIterator<Item = (&'a ChunkType, &'a [u8])>,
+{
+    let chunks: Vec<(&ChunkType, &[u8])> = chunks.into_iter().collect();
+    let types: Vec<ChunkType> = chunks.iter().map(|(chunk_type, _)| **chunk_type).collect();
+    let mut diagnostics = Vec::new();
+
+    let header = match chunks.first() {
+        Some((chunk_type, data)) if **chunk_type == ChunkType::IHDR => {
+            match Ihdr::from_bytes(data) {
+                Ok(header) => Some(header),
+                Err(crate::Error::InvalidHeader(reason)) => {
+                    diagnostics.push(Diagnostic::InvalidHeader(reason));
+                    None
+                }
+                Err(_) => None,
+            }
+        }
+        _ => {
+            diagnostics.push(Diagnostic::HeaderNotFirst);
+            None
+        }
+    };
+    if types.last() != Some(&ChunkType::IEND) {
+        diagnostics.push(Diagnostic::EndNotLast);
+    }
+
+    let position = |wanted: ChunkType| types.iter().position(|t| *t == wanted);
+    let first_idat = position(ChunkType::IDAT);
+    let palette = position(ChunkType::PLTE);
+
+    match first_idat {
+        None => diagnostics.push(Diagnostic::MissingImageData),
+        Some(first) => {
+            let mut previous = first;
+            for (index, chunk_type) in types.iter().enumerate().skip(first + 1) {
+                if *chunk_type == ChunkType::IDAT {
+                    if previous + 1 != index {
+                        diagnostics.push(Diagnostic::NonContiguousImageData { index });
+                    }
+                    previous = index;
+                }
+            }
+        }
+    }
+
+    if let Some(header) = header {
+        match (header.color_type, palette) {
+            (ColorType::Indexed, None) => diagnostics.push(Diagnostic::MissingPalette),
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(index)) => {
+                diagnostics.push(Diagnostic::UnexpectedPalette { index })
+            }
+            _ => {}
+        }
+    }
+
+    for (index, &chunk_type) in types.iter().enumerate() {
+        let earlier = &types[..index];
+        let misplaced = |rule| Diagnostic::MisplacedChunk {
+            chunk_type,
+            index,
+            rule,
+        };
+
+        let unique = CRITICAL_CHUNKS.contains(&chunk_type) && chunk_type != ChunkType::IDAT
+            || UNIQUE_CHUNKS.contains(&chunk_type);
+        if unique && earlier.contains(&chunk_type) {
+            diagnostics.push(Diagnostic::DuplicateChunk { chunk_type, index });
+        }
+
+        let after_idat = first_idat.is_some_and(|first| index > first);
+        if chunk_type == ChunkType::PLTE && after_idat {
+            diagnostics.push(misplaced("before IDAT"));
+        }
+        if BEFORE_PLTE.contains(&chunk_type) {
+            if palette.is_some_and(|palette| index > palette) {
+                diagnostics.push(misplaced("before PLTE"));
+            } else if after_idat {
+                diagnostics.push(misplaced("before IDAT"));
+            }
+        }
+        if AFTER_PLTE.contains(&chunk_type) {
+            if palette.is_some_and(|palette| index < palette) {
+                diagnostics.push(misplaced("after PLTE"));
+            } else if after_idat {
+                diagnostics.push(misplaced("before IDAT"));
+            }
+        }
+        if BEFORE_IDAT.contains(&chunk_type) && after_idat {
+            diagnostics.push(misplaced("before IDAT"));
+        }
+        if chunk_type == ChunkType::HIST && palette.is_none() {
+            diagnostics.push(Diagnostic::HistogramWithoutPalette { index });
+        }
+        if chunk_type.is_critical() && !CRITICAL_CHUNKS.contains(&chunk_type) {
+            diagnostics.push(Diagnostic::UnknownCriticalChunk { chunk_type, index });
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::png::Png;
+    use std::str::FromStr;
+
+    fn png_with(types: &[&str], color_type: ColorType) -> Png {
+        let chunks = types
+            .iter()
+            .map(|&name| {
+                let data = match name {
+                    "IHDR" => Ihdr::new(1, 1, 8, color_type).to_bytes().to_vec(),
+                    _ => Vec::new(),
+                };
+                Chunk::new(ChunkType::from_str(name).unwrap(), data)
+            })
+            .collect();
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_valid_layouts() {
+        let png = png_with(
+            &[
+                "IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "tEXt", "IEND",
+            ],
+            ColorType::Indexed,
+        );
+        assert_eq!(png.validate(), vec![]);
+        let png = png_with(&["IHDR", "tEXt", "IDAT", "tIME", "IEND"], ColorType::Rgb);
+        assert_eq!(png.validate(), vec![]);
+    }
+
+    #[test]
+    fn test_header_and_end_placement() {
+        let png = png_with(&["IDAT", "IHDR", "IEND", "tEXt"], ColorType::Rgb);
+        assert_eq!(
+            png.validate(),
+            vec![Diagnostic::HeaderNotFirst, Diagnostic::EndNotLast]
+        );
+
+        let png = png_with(&["IHDR", "IDAT", "IEND", "IEND"], ColorType::Rgb);
+        assert!(matches!(
+            png.validate()[..],
+            [Diagnostic::DuplicateChunk { index: 3, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_image_data_rules() {
+        let png = png_with(&["IHDR", "IEND"], ColorType::Rgb);
+        assert_eq!(png.validate(), vec![Diagnostic::MissingImageData]);
+
+        let png = png_with(&["IHDR", "IDAT", "tEXt", "IDAT", "IEND"], ColorType::Rgb);
+        assert_eq!(
+            png.validate(),
+            vec![Diagnostic::NonContiguousImageData { index: 3 }]
+        );
+    }
+
+    #[test]
+    fn test_palette_rules() {
+        let png = png_with(&["IHDR", "IDAT", "IEND"], ColorType::Indexed);
+        assert_eq!(png.validate(), vec![Diagnostic::MissingPalette]);
+
+        let png = png_with(&["IHDR", "PLTE", "IDAT", "IEND"], ColorType::Grayscale);
+        assert_eq!(
+            png.validate(),
+            vec![Diagnostic::UnexpectedPalette { index: 1 }]
+        );
+
+        let png = png_with(&["IHDR", "IDAT", "PLTE", "IEND"], ColorType::Rgb);
+        assert!(matches!(
+            png.validate()[..],
+            [Diagnostic::MisplacedChunk { index: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_ancillary_rules() {
+        let png = png_with(
+            &["IHDR", "tRNS", "PLTE", "gAMA", "IDAT", "pHYs", "IEND"],
+            ColorType::Rgb,
+        );
+        let rules: Vec<(usize, &str)> = png
+            .validate()
+            .into_iter()
+            .map(|diagnostic| match diagnostic {
+                Diagnostic::MisplacedChunk { index, rule, .. } => (index, rule),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            rules,
+            vec![(1, "after PLTE"), (3, "before PLTE"), (5, "before IDAT")]
+        );
+
+        let png = png_with(&["IHDR", "tIME", "IDAT", "tIME", "IEND"], ColorType::Rgb);
+        assert!(matches!(
+            png.validate()[..],
+            [Diagnostic::DuplicateChunk { index: 3, .. }]
+        ));
+
+        let png = png_with(&["IHDR", "hIST", "IDAT", "IEND"], ColorType::Rgb);
+        assert_eq!(
+            png.validate(),
+            vec![Diagnostic::HistogramWithoutPalette { index: 1 }]
+        );
+
+        let png = png_with(&["IHDR", "IDAT", "RUST", "IEND"], ColorType::Rgb);
+        assert!(matches!(
+            png.validate()[..],
+            [Diagnostic::UnknownCriticalChunk { index: 2, .. }]
+        ));
+    }
+}
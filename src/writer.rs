@@ -0,0 +1,50 @@
+use std::io::Write;
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::Result;
+
+/// Writes a PNG one chunk at a time to any `Write` sink.
+pub struct PngWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PngWriter<W> {
+    /// Writes the PNG signature.
+    pub fn new(mut inner: W) -> Result<Self> {
+        inner.write_all(&Png::STANDARD_HEADER)?;
+        Ok(PngWriter { inner })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        chunk.write_to(&mut self.inner)
+    }
+
+    /// Flushes the sink and gives it back.
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_writes_signature_and_chunks() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"hello".to_vec());
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        writer.write_chunk(&chunk).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let expected: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(chunk.as_bytes())
+            .collect();
+        assert_eq!(bytes, expected);
+    }
+}